@@ -0,0 +1,259 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// Compares the time taken and the peak heap usage of the streaming XML parser
+// with deserializing the whole document at once, on a synthetic topology of
+// about 5000 vertices: two initiators, each with a root expander cascaded to
+// 50 JBODs of 24 disks.  Run with "cargo bench".
+//
+extern crate sastopo2svg;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{BufReader, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const INITIATORS: usize = 2;
+const JBODS: usize = 50;
+const DISKS: usize = 24;
+const ITERATIONS: u32 = 5;
+
+type Parser = fn(BufReader<fs::File>) -> Result<usize, Box<dyn Error>>;
+
+//
+// Keeps track of the number of bytes allocated on the heap, and the most that
+// has been allocated at once.
+//
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(now, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn propgroup(name: &str, props: &[(&str, String)]) -> String {
+    let mut pg = format!(
+        "<nvlist><nvpair name=\"property-group-name\" type=\"string\" value=\"{}\"/>\
+         <nvpair name=\"property-values\" type=\"nvlist-array\">",
+        name
+    );
+    for (prop, value) in props {
+        pg.push_str(&format!(
+            "<nvlist><nvpair name=\"property-name\" type=\"string\" value=\"{}\"/>",
+            prop
+        ));
+        if value.contains(',') {
+            pg.push_str("<nvpair name=\"property-value\" type=\"uint32-array\">");
+            for elem in value.split(',') {
+                pg.push_str(&format!("<nvpair value=\"{}\"/>", elem));
+            }
+            pg.push_str("</nvpair>");
+        } else {
+            pg.push_str(&format!(
+                "<nvpair name=\"property-value\" type=\"string\" value=\"{}\"/>",
+                value
+            ));
+        }
+        pg.push_str("</nvlist>");
+    }
+    pg.push_str("</nvpair></nvlist>");
+    pg
+}
+
+fn vertex(out: &mut String, name: &str, instance: usize, fmri: &str, pg: String, edges: &[String]) {
+    out.push_str(&format!(
+        "<vertex name=\"{}\" instance=\"0x{:x}\" fmri=\"{}\">\
+         <nvpair name=\"property-groups\" type=\"nvlist-array\">{}{}</nvpair>",
+        name,
+        instance,
+        fmri,
+        propgroup("protocol", &[("resource", fmri.to_string())]),
+        pg
+    ));
+    if !edges.is_empty() {
+        out.push_str("<outgoing-edges>");
+        for edge in edges {
+            out.push_str(&format!("<edge fmri=\"{}\"/>", edge));
+        }
+        out.push_str("</outgoing-edges>");
+    }
+    out.push_str("</vertex>");
+}
+
+fn port(out: &mut String, instance: usize, local: &str, attached: &str, phys: usize, edge: String) {
+    let rates = vec!["11"; phys].join(",");
+    let errors = vec!["0x0"; phys].join(",");
+    let fmri = format!("sas://:start-phy=0:end-phy={}/port={}", phys - 1, local);
+    let pg = propgroup(
+        "sas-port",
+        &[
+            ("local-sas-address", local.to_string()),
+            ("attached-sas-address", attached.to_string()),
+            ("max-link-rate", rates.clone()),
+            ("negotiated-link-rate", rates),
+            ("invalid-dword", errors.clone()),
+            ("running-disparity-error", errors.clone()),
+            ("loss-dword-sync", errors.clone()),
+            ("reset-problem-count", errors),
+        ],
+    );
+    vertex(out, "port", instance, &fmri, pg, &[edge]);
+}
+
+//
+// Generates the synthetic topology and returns it and its number of vertices.
+//
+fn synthetic_topology() -> (String, usize) {
+    let mut vertices = String::new();
+    let mut count = 0;
+
+    for hba in 0..INITIATORS {
+        let hba_addr = format!("50030480{:08x}", hba);
+        let root_addr = format!("50030481{:08x}", hba);
+        let hba_port = format!("sas://:start-phy=0:end-phy=3/port={}", hba_addr);
+        let root_fmri = format!("sas:///expander={}", root_addr);
+
+        let pg = propgroup(
+            "initiator",
+            &[
+                ("manufacturer", "LSI".to_string()),
+                ("model", "SAS3008".to_string()),
+                ("location", format!("SLOT {}", hba)),
+            ],
+        );
+        vertex(&mut vertices, "initiator", hba, &format!("sas:///initiator={}", hba_addr), pg, &[hba_port]);
+        port(&mut vertices, hba, &hba_addr, &root_addr, 4, root_fmri.clone());
+
+        let mut root_ports = Vec::new();
+        for jbod in 0..JBODS {
+            let jbod_addr = format!("50030482{:04x}{:04x}", hba, jbod);
+            let local = format!("{}-{}", root_addr, jbod);
+            root_ports.push(format!("sas://:start-phy=0:end-phy=3/port={}", local));
+            port(&mut vertices, jbod, &local, &jbod_addr, 4, format!("sas:///expander={}", jbod_addr));
+        }
+        let pg = propgroup("expander", &[("model", "SAS3x40".to_string())]);
+        vertex(&mut vertices, "expander", hba, &root_fmri, pg, &root_ports);
+        count += 3 + JBODS;
+
+        for jbod in 0..JBODS {
+            let jbod_addr = format!("50030482{:04x}{:04x}", hba, jbod);
+            let mut jbod_ports = Vec::new();
+            for disk in 0..DISKS {
+                let disk_addr = format!("5000c5{:02x}{:04x}{:04x}", hba, jbod, disk);
+                let local = format!("{}-{}", jbod_addr, disk);
+                jbod_ports.push(format!("sas://:start-phy=0:end-phy=0/port={}", local));
+                port(&mut vertices, disk, &local, &disk_addr, 1, format!("sas:///target={}", disk_addr));
+
+                let pg = propgroup(
+                    "target",
+                    &[
+                        ("manufacturer", "SEAGATE".to_string()),
+                        ("model", "ST8000NM".to_string()),
+                        ("serial-number", format!("ZA{:02}{:02}{:02}", hba, jbod, disk)),
+                        ("location", format!("JBOD {} Disk {}", jbod, disk)),
+                    ],
+                );
+                vertex(&mut vertices, "target", disk, &format!("sas:///target={}", disk_addr), pg, &[]);
+            }
+            let pg = propgroup("expander", &[("model", "SAS3x40".to_string())]);
+            vertex(&mut vertices, "expander", jbod, &format!("sas:///expander={}", jbod_addr), pg, &jbod_ports);
+            count += 1 + 2 * DISKS;
+        }
+    }
+
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <topo-digraph fmri-scheme=\"sas\" nodename=\"bench\" os-version=\"bench\" \
+         product-id=\"bench\" timestamp=\"2023-01-01T00:00:00Z\">\
+         <vertices>{}</vertices></topo-digraph>\n",
+        vertices
+    );
+    (xml, count)
+}
+
+//
+// Parses the file at path ITERATIONS times with the specified parser and
+// returns the fastest time and the peak heap usage over and above what was
+// already allocated.
+//
+fn measure(path: &str, vertices: usize, parse: Parser) -> Result<(Duration, usize), Box<dyn Error>> {
+    let mut fastest = Duration::from_secs(u64::MAX);
+    let mut peak = 0;
+
+    for _ in 0..ITERATIONS {
+        let input = BufReader::new(fs::File::open(path)?);
+        let baseline = ALLOCATED.load(Ordering::SeqCst);
+        PEAK.store(baseline, Ordering::SeqCst);
+
+        let start = Instant::now();
+        let parsed = parse(input)?;
+        let elapsed = start.elapsed();
+
+        assert_eq!(parsed, vertices, "parser lost vertices");
+        fastest = fastest.min(elapsed);
+        peak = peak.max(PEAK.load(Ordering::SeqCst) - baseline);
+    }
+    Ok((fastest, peak))
+}
+
+fn main() {
+    let (xml, vertices) = synthetic_topology();
+    let path = env::temp_dir().join(format!("sastopo2svg-bench-{}.xml", process::id()));
+    let path = path.to_str().unwrap().to_string();
+    fs::File::create(&path)
+        .and_then(|mut file| file.write_all(xml.as_bytes()))
+        .expect("failed to write synthetic topology");
+    println!(
+        "synthetic topology: {} vertices, {} KiB of XML, best of {} runs\n",
+        vertices,
+        xml.len() / 1024,
+        ITERATIONS
+    );
+    drop(xml);
+
+    println!("{:<12} {:>12} {:>16}", "parser", "time (ms)", "peak heap (KiB)");
+    let parsers: [(&str, Parser); 2] = [
+        ("streaming", sastopo2svg::bench::parse_streaming),
+        ("tree", sastopo2svg::bench::parse_tree),
+    ];
+    for (name, parse) in parsers.iter() {
+        match measure(&path, vertices, *parse) {
+            Ok((time, peak)) => println!(
+                "{:<12} {:>12.1} {:>16}",
+                name,
+                time.as_secs_f64() * 1000.0,
+                peak / 1024
+            ),
+            Err(e) => eprintln!("{} parser failed: {}", name, e),
+        }
+    }
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,440 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// Batch mode renders the snapshots of many hosts at once, each into its own
+// subdirectory of the output directory and several at a time.  The images
+// are copied to an assets directory at the top of the output directory once,
+// and shared by all of the webpages, rather than copied for every host.  An
+// index page at the top of the output directory summarizes every host and
+// links to its webpage.
+//
+use log::debug;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::{escape, Assets, Config, HostSummary, SimpleError, HTML_FILE};
+
+//
+// Compressed snapshots are recognized by their contents, but snapshots in a
+// directory are found by their file names, which end in one of these.
+//
+const SNAPSHOT_SUFFIXES: &[&str] = &[".xml.gz", ".xml.zst", ".xml.xz", ".xml"];
+
+//
+// Names that snapshots can't have, because their subdirectories would clash
+// with the shared assets directory or the index page.
+//
+const RESERVED_NAMES: &[&str] = &["assets", "index.html"];
+
+const INDEX_TEMPLATE: &str = include_str!("index.html");
+const HOSTS_MARKER: &str = "<!-- HOSTS -->";
+
+#[derive(Debug)]
+pub struct BatchResult {
+    // name of the subdirectory the snapshot was rendered into
+    pub name: String,
+    pub xml_path: String,
+    // the summary of the host, if the snapshot was rendered
+    pub summary: Option<HostSummary>,
+    // what went wrong, if the snapshot couldn't be rendered
+    pub error: Option<String>,
+}
+
+//
+// Returns the name of the snapshot in the named file, which is the file name
+// without its suffix.
+//
+fn snapshot_name(file_name: &str) -> Option<&str> {
+    SNAPSHOT_SUFFIXES
+        .iter()
+        .find_map(|suffix| file_name.strip_suffix(suffix))
+        .filter(|name| !name.is_empty())
+}
+
+//
+// Returns the paths of the snapshots in the specified directory, or of the
+// files matching the specified glob pattern, in order.
+//
+pub fn batch_inputs(input: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut paths = Vec::new();
+    if Path::new(input).is_dir() {
+        for entry in fs::read_dir(input)? {
+            let path = entry?.path();
+            let is_snapshot = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => snapshot_name(name).is_some(),
+                None => false,
+            };
+            if is_snapshot && path.is_file() {
+                paths.push(path.to_string_lossy().into_owned());
+            }
+        }
+    } else {
+        for entry in glob::glob(input)? {
+            let path = entry?;
+            if path.is_file() {
+                paths.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(Box::new(SimpleError(format!("no snapshots found in {}", input))));
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+//
+// Renders each of the snapshots into a subdirectory of config.outdir named
+// after the snapshot, using up to jobs threads.  Returns the outcome for each
+// snapshot, in order of name.  Failing to render a snapshot doesn't stop the
+// others from being rendered.
+//
+pub fn run_batch(
+    config: &Config,
+    xml_paths: &[String],
+    jobs: usize,
+) -> Result<Vec<BatchResult>, Box<dyn Error>> {
+    let mut names = HashSet::new();
+    let mut inputs = Vec::new();
+    for path in xml_paths {
+        let file_name = match Path::new(path).file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name,
+            None => {
+                return Err(Box::new(SimpleError(format!("invalid snapshot path: {}", path))))
+            }
+        };
+        let name = snapshot_name(file_name).unwrap_or(file_name).to_string();
+        if name.starts_with('.') || RESERVED_NAMES.contains(&name.as_str()) {
+            return Err(Box::new(SimpleError(format!(
+                "{}: a snapshot can't be named {}",
+                path, name
+            ))));
+        }
+        if !names.insert(name.clone()) {
+            return Err(Box::new(SimpleError(format!(
+                "more than one snapshot is named {}",
+                name
+            ))));
+        }
+        inputs.push((name, path.clone()));
+    }
+
+    //
+    // Copy all of the images, for every chassis, to the shared assets
+    // directory.
+    //
+    let assets = Assets::new(config, false)?;
+    let asset_dst_dir = format!("{}/assets", config.outdir);
+    fs::create_dir_all(&asset_dst_dir)?;
+    let mut options = fs_extra::dir::CopyOptions::new();
+    options.overwrite = true;
+    options.content_only = true;
+    debug!("Copying image assets: {} to {}", assets.src_dir, asset_dst_dir);
+    fs_extra::dir::copy(&assets.src_dir, &asset_dst_dir, &options)?;
+
+    //
+    // Each thread takes the next snapshot that hasn't been rendered yet until
+    // there are none left.
+    //
+    let inputs = Arc::new(inputs);
+    let next = Arc::new(AtomicUsize::new(0));
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let mut threads = Vec::new();
+    for _ in 0..jobs.max(1).min(inputs.len()) {
+        let config = config.clone();
+        let inputs = Arc::clone(&inputs);
+        let next = Arc::clone(&next);
+        let results = Arc::clone(&results);
+        threads.push(thread::spawn(move || {
+            while let Some((name, xml_path)) = inputs.get(next.fetch_add(1, Ordering::SeqCst)) {
+                let mut host_config = config.clone();
+                host_config.outdir = format!("{}/{}", config.outdir, name);
+                host_config.xml_path = xml_path.clone();
+                host_config.shared_assets = Some("../assets".to_string());
+                host_config.format = None;
+
+                debug!("Rendering {} to {}", xml_path, host_config.outdir);
+                let rendered = super::load_digraph(&host_config).and_then(|mut digraph| {
+                    let summary = HostSummary::new(&digraph);
+                    super::build_webpage(&host_config, &mut digraph)?;
+                    Ok(summary)
+                });
+                let (summary, error) = match rendered {
+                    Ok(summary) => (Some(summary), None),
+                    Err(e) => (None, Some(e.to_string())),
+                };
+                results.lock().unwrap().push(BatchResult {
+                    name: name.clone(),
+                    xml_path: xml_path.clone(),
+                    summary,
+                    error,
+                });
+            }
+        }));
+    }
+    for thread in threads {
+        if thread.join().is_err() {
+            return Err(Box::new(SimpleError("a batch thread panicked".to_string())));
+        }
+    }
+
+    let mut results = match Arc::try_unwrap(results) {
+        Ok(results) => results.into_inner().unwrap(),
+        Err(_) => return Err(Box::new(SimpleError("batch results still shared".to_string()))),
+    };
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let index_path = format!("{}/index.html", config.outdir);
+    debug!("Saving fleet index to {}", index_path);
+    fs::write(&index_path, build_index(&results))?;
+
+    Ok(results)
+}
+
+//
+// Percent-encodes everything but unreserved characters in the specified path
+// segment, so a snapshot name can be used in a link whatever it contains.
+//
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+//
+// Creates a table cell holding a count, highlighted if it counts problems
+// and isn't zero.
+//
+fn count_cell(count: usize, problem: bool) -> String {
+    if problem && count > 0 {
+        format!("<td class=\"count problem\">{}</td>", count)
+    } else {
+        format!("<td class=\"count\">{}</td>", count)
+    }
+}
+
+//
+// Generates the fleet index page, with a row for each snapshot linking to
+// its webpage.  Snapshots that couldn't be rendered get a row showing why,
+// in place of the product, with the same number of cells so that it sorts
+// along with the others.
+//
+fn build_index(results: &[BatchResult]) -> String {
+    let mut rows = String::new();
+    for result in results {
+        match &result.summary {
+            Some(summary) => {
+                let nodename = if summary.nodename.is_empty() {
+                    &result.name
+                } else {
+                    &summary.nodename
+                };
+                rows.push_str(&format!(
+                    "        <tr><td><a href=\"{}/{}\">{}</a></td>\
+                     <td>{}</td><td>{}</td><td>{}</td>{}{}{}</tr>\n",
+                    encode_path_segment(&result.name),
+                    HTML_FILE,
+                    escape(nodename),
+                    escape(&summary.product_id),
+                    escape(&summary.os_version),
+                    escape(&summary.timestamp),
+                    count_cell(summary.disks, false),
+                    count_cell(summary.degraded_ports, true),
+                    count_cell(summary.single_pathed_disks, true),
+                ));
+            }
+            None => {
+                rows.push_str(&format!(
+                    "        <tr class=\"failed\"><td>{}</td><td>{}</td><td></td><td></td>\
+                     <td class=\"count\"></td><td class=\"count\"></td>\
+                     <td class=\"count\"></td></tr>\n",
+                    escape(&result.name),
+                    escape(result.error.as_deref().unwrap_or("not rendered")),
+                ));
+            }
+        }
+    }
+    INDEX_TEMPLATE.replace(HOSTS_MARKER, rows.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        batch_inputs, build_index, encode_path_segment, run_batch, snapshot_name, BatchResult,
+        Config, HostSummary, HTML_FILE,
+    };
+
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    //
+    // Creates an empty scratch directory for the named test.
+    //
+    fn scratch_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sastopo2svg-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn paths(dir: &Path, names: &[&str]) -> Vec<String> {
+        names
+            .iter()
+            .map(|name| dir.join(name).to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_suffixes() {
+        assert_eq!(snapshot_name("cn1.xml"), Some("cn1"));
+        assert_eq!(snapshot_name("cn1.xml.gz"), Some("cn1"));
+        assert_eq!(snapshot_name("cn1.xml.zst"), Some("cn1"));
+        assert_eq!(snapshot_name("cn1.xml.xz"), Some("cn1"));
+        assert_eq!(snapshot_name("cn1.2023.xml"), Some("cn1.2023"));
+        assert_eq!(snapshot_name("cn1.gz"), None);
+        assert_eq!(snapshot_name("notes.txt"), None);
+        assert_eq!(snapshot_name(".xml"), None);
+    }
+
+    //
+    // A directory is searched for snapshots by suffix, whereas a glob takes
+    // every file that matches it.  Neither picks up directories.
+    //
+    #[test]
+    fn directory_and_glob_inputs() {
+        let dir = scratch_dir("batch-inputs");
+        for name in &["cn2.xml.gz", "cn1.xml", "cn3.xml.zst", "cn4.xml.xz", "notes.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        fs::create_dir(dir.join("cn5.xml")).unwrap();
+
+        assert_eq!(
+            batch_inputs(dir.to_str().unwrap()).unwrap(),
+            paths(&dir, &["cn1.xml", "cn2.xml.gz", "cn3.xml.zst", "cn4.xml.xz"])
+        );
+        assert_eq!(
+            batch_inputs(dir.join("*").to_str().unwrap()).unwrap(),
+            paths(&dir, &["cn1.xml", "cn2.xml.gz", "cn3.xml.zst", "cn4.xml.xz", "notes.txt"])
+        );
+        assert_eq!(
+            batch_inputs(dir.join("cn[12]*").to_str().unwrap()).unwrap(),
+            paths(&dir, &["cn1.xml", "cn2.xml.gz"])
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn no_inputs() {
+        let dir = scratch_dir("batch-no-inputs");
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        assert!(batch_inputs(dir.to_str().unwrap()).is_err());
+        assert!(batch_inputs(dir.join("*.xml").to_str().unwrap()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    //
+    // Snapshots whose subdirectories would be the same, or would clash with
+    // the rest of the output, are refused before anything is written.
+    //
+    #[test]
+    fn conflicting_names() {
+        let dir = scratch_dir("batch-names");
+        let outdir = dir.join("out");
+        let config = Config::new(outdir.to_string_lossy().into_owned(), String::new());
+
+        let conflicts: &[&[&str]] = &[
+            &["a/cn1.xml", "b/cn1.xml.gz"],
+            &["cn1.xml", "cn1.xml.zst"],
+            &["assets.xml"],
+            &["index.html.xml"],
+            &[".xml"],
+            &[".hidden.xml"],
+            &["..xml"],
+            &["...xml.gz"],
+        ];
+        for names in conflicts {
+            let xml_paths = paths(&dir, names);
+            assert!(run_batch(&config, &xml_paths, 1).is_err(), "{:?}", names);
+        }
+        assert!(!outdir.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn path_segments() {
+        assert_eq!(encode_path_segment("cn1.2023_a-b~c"), "cn1.2023_a-b~c");
+        assert_eq!(encode_path_segment(".."), "..");
+        assert_eq!(encode_path_segment("a b/c?d#e%f"), "a%20b%2Fc%3Fd%23e%25f");
+        assert_eq!(encode_path_segment("\"><x>"), "%22%3E%3Cx%3E");
+        assert_eq!(encode_path_segment("caf\u{e9}"), "caf%C3%A9");
+    }
+
+    //
+    // Everything taken from a snapshot, or its file name, is escaped in the
+    // index page, and the links can't be broken out of.
+    //
+    #[test]
+    fn index_escaping() {
+        let hostile_name = "\"><script>alert(1)</script>";
+        let results = vec![
+            BatchResult {
+                name: hostile_name.to_string(),
+                xml_path: String::new(),
+                summary: Some(HostSummary {
+                    product_id: "' onmouseover='alert(2)".to_string(),
+                    nodename: "<script>alert(3)</script>".to_string(),
+                    os_version: "</td></tr><tr><td>".to_string(),
+                    timestamp: "&lt;".to_string(),
+                    disks: 1,
+                    degraded_ports: 0,
+                    single_pathed_disks: 1,
+                }),
+                error: None,
+            },
+            BatchResult {
+                name: "<img src=x onerror=alert(4)>".to_string(),
+                xml_path: String::new(),
+                summary: None,
+                error: Some("<b>alert(5)</b>".to_string()),
+            },
+        ];
+        let index = build_index(&results);
+
+        for injected in &["<script>alert", "' onmouseover", "</td></tr><tr>", "<img", "<b>"] {
+            assert!(!index.contains(injected), "{}", injected);
+        }
+        assert!(index.contains(&format!(
+            "<a href=\"%22%3E%3Cscript%3Ealert%281%29%3C%2Fscript%3E/{}\">\
+             &lt;script&gt;alert(3)&lt;/script&gt;</a>",
+            HTML_FILE
+        )));
+        assert!(index.contains("<td>&apos; onmouseover=&apos;alert(2)</td>"));
+        assert!(index.contains("<td>&amp;lt;</td>"));
+        assert!(index.contains("<td>&lt;img src=x onerror=alert(4)&gt;</td>"));
+        assert!(index.contains("<td>&lt;b&gt;alert(5)&lt;/b&gt;</td>"));
+        assert!(index.contains("<td class=\"count problem\">1</td>"));
+    }
+}
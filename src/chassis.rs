@@ -0,0 +1,165 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// Each supported chassis has a directory under the assets directory holding
+// its images and a chassis.json manifest describing them, for example:
+//
+// {
+//     "name": "Joyent-M12G5",
+//     "product-ids": [ "Joyent-M12G5" ],
+//     "views": [
+//         {
+//             "name": "front",
+//             "image": "Joyent-M12G5-front.png",
+//             "width": 640,
+//             "height": 209
+//         }
+//     ],
+//     "slots": [
+//         {
+//             "location": "Front Disk 0",
+//             "view": "front",
+//             "x": 42,
+//             "y": 76,
+//             "width": 28,
+//             "height": 108
+//         }
+//     ]
+// }
+//
+// Each view is a picture of one side of the chassis.  Image paths are relative
+// to the chassis directory and slot coordinates are in pixels, relative to the
+// top left corner of the view the slot is visible in.  The name is only used
+// for display; images are always found through the directory the manifest
+// was loaded from.
+//
+use log::debug;
+
+use serde::Deserialize;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = "chassis.json";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChassisView {
+    pub name: String,
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChassisSlot {
+    // value of the location property of the vertex in this slot
+    pub location: String,
+    // name of the view this slot is visible in
+    pub view: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChassisManifest {
+    pub name: String,
+    // name of the directory, under the assets directory, the manifest and
+    // its images are in
+    #[serde(skip)]
+    pub dir: String,
+    pub product_ids: Vec<String>,
+    pub views: Vec<ChassisView>,
+    #[serde(default)]
+    pub slots: Vec<ChassisSlot>,
+}
+
+impl ChassisManifest {
+    pub fn from_file(path: &Path) -> Result<ChassisManifest, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let manifest: ChassisManifest = serde_json::from_str(&contents)?;
+        Ok(manifest)
+    }
+
+    pub fn view_slots<'a>(&'a self, view: &'a ChassisView) -> impl Iterator<Item = &'a ChassisSlot> {
+        self.slots.iter().filter(move |slot| slot.view == view.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct ChassisRegistry {
+    chassis: Vec<ChassisManifest>,
+}
+
+impl ChassisRegistry {
+    //
+    // Load the manifest of every chassis directory found under the specified
+    // assets directory.  Directories without a manifest (e.g. icons) are
+    // skipped.
+    //
+    pub fn load(asset_dir: &str) -> Result<ChassisRegistry, Box<dyn Error>> {
+        let mut chassis = Vec::new();
+
+        for entry in fs::read_dir(asset_dir)? {
+            let entry = entry?;
+            let manifest_path = entry.path().join(MANIFEST_FILE);
+            if !manifest_path.is_file() {
+                continue;
+            }
+            debug!("Loading chassis manifest: {}", manifest_path.display());
+            let mut manifest = ChassisManifest::from_file(&manifest_path)?;
+            manifest.dir = entry.file_name().to_string_lossy().into_owned();
+            chassis.push(manifest);
+        }
+        Ok(ChassisRegistry { chassis })
+    }
+
+    pub fn lookup(&self, product_id: &str) -> Option<&ChassisManifest> {
+        self.chassis
+            .iter()
+            .find(|c| c.product_ids.iter().any(|id| id == product_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChassisRegistry, MANIFEST_FILE};
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    //
+    // A manifest is tied to the directory it's in, whatever it's named.
+    //
+    #[test]
+    fn manifest_dir() {
+        let asset_dir = env::temp_dir().join(format!("sastopo2svg-chassis-{}", process::id()));
+        fs::create_dir_all(asset_dir.join("icons")).unwrap();
+        fs::create_dir_all(asset_dir.join("chassis-a")).unwrap();
+        fs::write(
+            asset_dir.join("chassis-a").join(MANIFEST_FILE),
+            r#"{ "name": "Chassis A", "product-ids": [ "A1", "A2" ], "views": [] }"#,
+        )
+        .unwrap();
+
+        let registry = ChassisRegistry::load(asset_dir.to_str().unwrap()).unwrap();
+        let manifest = registry.lookup("A2").unwrap();
+        assert_eq!(manifest.name, "Chassis A");
+        assert_eq!(manifest.dir, "chassis-a");
+        assert!(registry.lookup("B1").is_none());
+
+        fs::remove_dir_all(&asset_dir).unwrap();
+    }
+}
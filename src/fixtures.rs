@@ -0,0 +1,84 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// Small digraphs for the unit tests, built up a vertex at a time.
+//
+use std::collections::BTreeMap;
+
+use super::{PropertyGroup, SasDigraph, SasDigraphProperty, SasDigraphVertex, INITIATOR};
+
+pub struct DigraphBuilder {
+    digraph: SasDigraph,
+}
+
+impl DigraphBuilder {
+    pub fn new(product_id: &str) -> DigraphBuilder {
+        DigraphBuilder {
+            digraph: SasDigraph::new(
+                product_id.to_string(),
+                "host".to_string(),
+                "joyent_20230101T000000Z".to_string(),
+                "2023-01-01T00:00:00Z".to_string(),
+            ),
+        }
+    }
+
+    //
+    // Adds a vertex of the specified type, with the specified properties in a
+    // property group named after the type, and edges to the specified FMRIs.
+    //
+    pub fn vertex(
+        mut self,
+        name: &str,
+        fmri: &str,
+        props: &[(&str, &str)],
+        edges: &[&str],
+    ) -> DigraphBuilder {
+        let outgoing_edges = if edges.is_empty() {
+            None
+        } else {
+            Some(edges.iter().map(|edge| edge.to_string()).collect())
+        };
+        let mut vtx =
+            SasDigraphVertex::new(fmri.to_string(), name.to_string(), 0, outgoing_edges);
+        let mut pg = PropertyGroup::new(name.to_string());
+        for (prop, value) in props {
+            pg.properties
+                .push(SasDigraphProperty::new(prop.to_string(), value.to_string()));
+        }
+        vtx.propgroups.push(pg);
+
+        if name == INITIATOR {
+            self.digraph.initiators.push(fmri.to_string());
+        }
+        self.digraph.vertices.insert(fmri.to_string(), vtx);
+        self
+    }
+
+    pub fn build(self) -> SasDigraph {
+        self.digraph
+    }
+}
+
+//
+// Returns a dump of the digraph that doesn't depend on the order of its
+// vertices in the hashmap, for comparing digraphs loaded different ways.
+//
+pub fn canonical(digraph: &SasDigraph) -> String {
+    let vertices: BTreeMap<&String, &SasDigraphVertex> = digraph.vertices.iter().collect();
+    format!(
+        "{} {} {} {}\n{:?}\n{:#?}",
+        digraph.product_id,
+        digraph.nodename,
+        digraph.os_version,
+        digraph.timestamp,
+        digraph.initiators,
+        vertices
+    )
+}
@@ -0,0 +1,489 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// The digraph is laid out in ranks, one per depth from the initiators, with
+// the vertices at each depth placed side by side along the rank.  Ranks run
+// left to right (as columns) or top to bottom (as rows) depending on the
+// orientation.  Positions are computed along the rank axis and the sibling
+// axis and then mapped to x/y coordinates.
+//
+use svg::node::element::Path;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::str::FromStr;
+
+use super::{LinkSummary, SasDigraph, SasDigraphVertex};
+
+//
+// Space left below each vertex for its label
+//
+pub const LABEL_SPACE: u32 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Orientation {
+    LeftToRight,
+    TopToBottom,
+}
+
+impl FromStr for Orientation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Orientation, String> {
+        match s {
+            "lr" | "left-to-right" => Ok(Orientation::LeftToRight),
+            "tb" | "top-to-bottom" => Ok(Orientation::TopToBottom),
+            _ => Err(format!("invalid orientation: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LayoutConfig {
+    pub orientation: Orientation,
+    // width and height of the vertex icons
+    pub node_size: u32,
+    // gap between consecutive ranks
+    pub rank_spacing: u32,
+    // gap between vertices in the same rank
+    pub sibling_spacing: u32,
+    // space around the diagram
+    pub margin: u32,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> LayoutConfig {
+        LayoutConfig::new()
+    }
+}
+
+impl LayoutConfig {
+    pub fn new() -> LayoutConfig {
+        LayoutConfig {
+            orientation: Orientation::LeftToRight,
+            node_size: 120,
+            rank_spacing: 130,
+            sibling_spacing: 30,
+            margin: 50,
+        }
+    }
+
+    pub fn rank_pitch(&self) -> u32 {
+        self.node_size + self.rank_spacing
+    }
+
+    pub fn sibling_pitch(&self) -> u32 {
+        self.node_size + self.sibling_spacing
+    }
+
+    //
+    // Maps a position along the rank and sibling axes to x/y coordinates.
+    //
+    pub fn point(&self, rank: u32, sibling: u32) -> (u32, u32) {
+        match self.orientation {
+            Orientation::LeftToRight => (rank, sibling),
+            Orientation::TopToBottom => (sibling, rank),
+        }
+    }
+
+    //
+    // Maps x/y coordinates to a position along the rank and sibling axes.
+    //
+    pub fn axes(&self, x: u32, y: u32) -> (u32, u32) {
+        self.point(x, y)
+    }
+
+    //
+    // Returns the coordinates of the top left corner of the vertex at the
+    // specified index in the rank at the specified depth.  Ranks with fewer
+    // vertices than the tallest rank are spread out to line up with it.
+    //
+    pub(crate) fn place(&self, depth: u32, index: u32, rank_len: u32, max_height: u32) -> (u32, u32) {
+        let factor = match index {
+            0 => 1,
+            _ => cmp::max(1, max_height / rank_len),
+        };
+        let rank = ((depth - 1) * self.rank_pitch()) + self.margin;
+        let sibling = (index * self.sibling_pitch() * factor) + self.margin;
+        self.point(rank, sibling)
+    }
+
+    //
+    // Returns the position, along the rank and sibling axes, that edges leave
+    // the specified vertex from.  When ranks run top to bottom, edges leave
+    // from below the vertex label.
+    //
+    pub(crate) fn edge_start(&self, vtx: &SasDigraphVertex) -> (u32, u32) {
+        let (rank, sibling) = self.axes(vtx.geometry.x, vtx.geometry.y);
+        let rank = match self.orientation {
+            Orientation::LeftToRight => rank + self.node_size,
+            Orientation::TopToBottom => rank + self.node_size + LABEL_SPACE,
+        };
+        (rank, sibling + (self.node_size / 2))
+    }
+
+    //
+    // Returns the position, along the rank and sibling axes, that edges enter
+    // the specified vertex at.
+    //
+    pub(crate) fn edge_end(&self, vtx: &SasDigraphVertex) -> (u32, u32) {
+        let (rank, sibling) = self.axes(vtx.geometry.x, vtx.geometry.y);
+        (rank, sibling + (self.node_size / 2))
+    }
+
+    //
+    // Returns the space between ranks that is available for routing edges.
+    //
+    pub(crate) fn channel_space(&self) -> u32 {
+        match self.orientation {
+            Orientation::LeftToRight => self.rank_spacing,
+            Orientation::TopToBottom => self.rank_spacing.saturating_sub(LABEL_SPACE),
+        }
+    }
+}
+
+//
+// Tracks the extent of the elements placed in the drawing, so that the SVG can
+// be sized to fit them.
+//
+#[derive(Debug)]
+pub(crate) struct BoundingBox {
+    min_x: i64,
+    min_y: i64,
+    max_x: i64,
+    max_y: i64,
+}
+
+impl BoundingBox {
+    pub(crate) fn new() -> BoundingBox {
+        BoundingBox {
+            min_x: i64::MAX,
+            min_y: i64::MAX,
+            max_x: i64::MIN,
+            max_y: i64::MIN,
+        }
+    }
+
+    pub(crate) fn include(&mut self, x: i64, y: i64, width: i64, height: i64) {
+        self.min_x = cmp::min(self.min_x, x);
+        self.min_y = cmp::min(self.min_y, y);
+        self.max_x = cmp::max(self.max_x, x + width);
+        self.max_y = cmp::max(self.max_y, y + height);
+    }
+
+    //
+    // Includes a line of text of len characters anchored at (x, y), which is
+    // the middle or start of its baseline.  We don't have font metrics, so
+    // assume the average character is 0.6em wide.
+    //
+    pub(crate) fn include_text(&mut self, x: u32, y: u32, len: usize, font_size: u32, centered: bool) {
+        let width = (len as i64 * font_size as i64 * 6) / 10;
+        let left = if centered { x as i64 - (width / 2) } else { x as i64 };
+        self.include(left, y as i64 - font_size as i64, width, font_size as i64 + 4);
+    }
+
+    //
+    // Returns the x and y coordinates of the top left corner of the box, and
+    // its width and height, after growing it by margin on every side.
+    //
+    pub(crate) fn with_margin(&self, margin: u32) -> (i64, i64, i64, i64) {
+        let margin = margin as i64;
+        if self.min_x > self.max_x {
+            return (0, 0, 2 * margin, 2 * margin);
+        }
+        (
+            self.min_x - margin,
+            self.min_y - margin,
+            (self.max_x - self.min_x) + (2 * margin),
+            (self.max_y - self.min_y) + (2 * margin),
+        )
+    }
+}
+
+//
+// Assigns each vertex with outgoing edges a channel in the gap after its rank,
+// which its edges run along.  The channel of a vertex spans the positions of
+// the vertex and all of its destination vertices along the sibling axis, and
+// vertices whose spans overlap are given different channels so that their
+// edges don't overlap.  Returns a hash map of vertex FMRIs to their channel
+// number and the number of channels in that gap.
+//
+pub(crate) fn assign_channels(
+    layout: &LayoutConfig,
+    digraph: &SasDigraph,
+    column_hash: &HashMap<u32, Vec<String>>,
+    max_depth: u32,
+) -> HashMap<String, (u32, u32)> {
+    let mut channels = HashMap::new();
+
+    for depth in 1..=max_depth {
+        let mut spans: Vec<(u32, u32, &str)> = Vec::new();
+        for fmri in &column_hash[&depth] {
+            let vtx = &digraph.vertices[fmri];
+            let edges = match &vtx.outgoing_edges {
+                Some(edges) => edges,
+                None => continue,
+            };
+            let (_, mut start) = layout.edge_start(vtx);
+            let mut end = start;
+            for edge_fmri in edges {
+                let (_, pos) = layout.edge_end(&digraph.vertices[edge_fmri]);
+                start = cmp::min(start, pos);
+                end = cmp::max(end, pos);
+            }
+            spans.push((start, end, fmri));
+        }
+
+        //
+        // Greedy interval colouring: walk the spans in order and put each one
+        // in the first channel that is free by the time it starts.
+        //
+        spans.sort();
+        let mut channel_ends: Vec<u32> = Vec::new();
+        let mut assigned = Vec::new();
+        for (start, end, fmri) in spans {
+            let channel = match channel_ends.iter().position(|e| *e < start) {
+                Some(channel) => {
+                    channel_ends[channel] = end;
+                    channel
+                }
+                None => {
+                    channel_ends.push(end);
+                    channel_ends.len() - 1
+                }
+            };
+            assigned.push((fmri, channel as u32));
+        }
+        for (fmri, channel) in assigned {
+            channels.insert(fmri.to_string(), (channel, channel_ends.len() as u32));
+        }
+    }
+    channels
+}
+
+//
+// Converts a position along the rank and sibling axes, which has already been
+// clamped to the drawing, to x/y coordinates.
+//
+fn path_point(layout: &LayoutConfig, (rank, sibling): (i64, i64)) -> String {
+    let (x, y) = layout.point(
+        rank.try_into().unwrap_or(u32::MAX),
+        sibling.try_into().unwrap_or(u32::MAX),
+    );
+    format!("{} {}", x, y)
+}
+
+//
+// Returns the unit step from one point to another along whichever axis they
+// differ on, and the distance between them.
+//
+fn step((r1, s1): (i64, i64), (r2, s2): (i64, i64)) -> ((i64, i64), i64) {
+    (((r2 - r1).signum(), (s2 - s1).signum()), (r2 - r1).abs() + (s2 - s1).abs())
+}
+
+//
+// Creates the path data for a route made up of horizontal and vertical
+// segments through the specified points, with rounded corners.  Corners
+// are rounded by at most half of the segments they join, or all of the
+// first and last segments, so the radius can't be negative or overshoot.
+//
+fn route_data(layout: &LayoutConfig, route: &[(i64, i64)]) -> String {
+    const CORNER_RADIUS: i64 = 8;
+
+    //
+    // Drop repeated points and points in the middle of a straight line, so
+    // that only the corners are left between the ends.
+    //
+    let mut points: Vec<(i64, i64)> = Vec::new();
+    for point in route {
+        if points.last() == Some(point) {
+            continue;
+        }
+        if points.len() >= 2 {
+            let n = points.len();
+            if step(points[n - 2], points[n - 1]).0 == step(points[n - 1], *point).0 {
+                points.pop();
+            }
+        }
+        points.push(*point);
+    }
+
+    let mut data = vec![format!("M {}", path_point(layout, points[0]))];
+    for i in 1..points.len().saturating_sub(1) {
+        let (before, len_before) = step(points[i - 1], points[i]);
+        let (after, len_after) = step(points[i], points[i + 1]);
+        let len_before = if i == 1 { len_before } else { len_before / 2 };
+        let len_after = if i + 2 == points.len() { len_after } else { len_after / 2 };
+        let r = cmp::min(len_before, len_after).clamp(0, CORNER_RADIUS);
+
+        let (rank, sibling) = points[i];
+        let from = (rank - (before.0 * r), sibling - (before.1 * r));
+        let to = (rank + (after.0 * r), sibling + (after.1 * r));
+        data.push(format!("L {}", path_point(layout, from)));
+        data.push(format!(
+            "Q {} {}",
+            path_point(layout, points[i]),
+            path_point(layout, to)
+        ));
+    }
+    if let Some(last) = points.last().filter(|_| points.len() > 1) {
+        data.push(format!("L {}", path_point(layout, *last)));
+    }
+    data.join(" ")
+}
+
+//
+// Creates the path for an edge that leaves its source vertex at start, runs
+// along the channel at position channel on the rank axis, and enters its
+// destination vertex at end, with rounded corners.  Edges from ports are
+// styled according to the state of the link: the stroke width grows with the
+// aggregate bandwidth and degraded links are dashed.  The route is added to
+// the bounding box.
+//
+// A multipathed vertex is placed in the rank it is first reached at, so it
+// may be reached by an edge from a vertex in the same or a later rank.  Such
+// edges loop back, through the gap between the destination and its sibling
+// on the source's side, to come into the destination from the gap before
+// its rank.
+//
+pub(crate) fn edge_path(
+    layout: &LayoutConfig,
+    start: (u32, u32),
+    channel: u32,
+    end: (u32, u32),
+    link: Option<&LinkSummary>,
+    bbox: &mut BoundingBox,
+) -> Path {
+    let (r1, s1) = (start.0 as i64, start.1 as i64);
+    let (r2, s2) = (end.0 as i64, end.1 as i64);
+    let rc = channel as i64;
+
+    //
+    // Work out the route along the rank and sibling axes.  Routes can't go
+    // above or to the left of the drawing, so they're clamped to it.
+    //
+    let route = if r2 > rc {
+        vec![(r1, s1), (rc, s1), (rc, s2), (r2, s2)]
+    } else {
+        let node_size = layout.node_size as i64;
+        let sibling_gap = layout.sibling_spacing as i64;
+        let loop_rank = r2 - (layout.channel_space() as i64 / 2);
+        let loop_sibling = if s1 > s2 {
+            s2 + (node_size / 2) + (sibling_gap / 2)
+        } else {
+            s2 - (node_size / 2) - (sibling_gap / 2)
+        };
+        vec![
+            (r1, s1),
+            (rc, s1),
+            (rc, loop_sibling),
+            (loop_rank, loop_sibling),
+            (loop_rank, s2),
+            (r2, s2),
+        ]
+    };
+    let route: Vec<(i64, i64)> = route
+        .into_iter()
+        .map(|(rank, sibling)| (cmp::max(rank, 0), cmp::max(sibling, 0)))
+        .collect();
+    for (rank, sibling) in &route {
+        let (x, y) = match layout.orientation {
+            Orientation::LeftToRight => (*rank, *sibling),
+            Orientation::TopToBottom => (*sibling, *rank),
+        };
+        bbox.include(x, y, 0, 0);
+    }
+    let data = route_data(layout, &route);
+
+    let path = Path::new()
+        .set("class", "edge")
+        .set("d", data)
+        .set("fill", "none")
+        .set("stroke", "black");
+
+    match link {
+        Some(link) => {
+            let path = path.set("stroke-width", 2.0 + (link.bandwidth / 12.0));
+            if link.degraded {
+                path.set("stroke-dasharray", "8 4")
+            } else {
+                path
+            }
+        }
+        None => path.set("stroke-width", "2"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edge_path, BoundingBox, LayoutConfig, Orientation};
+
+    //
+    // Returns the coordinates in the path data of an edge, as pairs.
+    //
+    fn path_points(path: &svg::node::element::Path) -> Vec<(i64, i64)> {
+        let path = path.to_string();
+        let start = path.find(" d=\"").unwrap() + 4;
+        let data = &path[start..start + path[start..].find('"').unwrap()];
+        let coords: Vec<i64> = data
+            .split_whitespace()
+            .filter_map(|token| token.parse::<i64>().ok())
+            .collect();
+        coords.chunks(2).map(|pair| (pair[0], pair[1])).collect()
+    }
+
+    #[test]
+    fn edge_to_later_rank() {
+        let layout = LayoutConfig::new();
+        let mut bbox = BoundingBox::new();
+        let path = edge_path(&layout, (170, 110), 235, (300, 260), None, &mut bbox);
+        let points = path_points(&path);
+        assert_eq!(points.first(), Some(&(170, 110)));
+        assert_eq!(points.last(), Some(&(300, 260)));
+        assert!(points.iter().all(|(x, _)| *x >= 170 && *x <= 300));
+    }
+
+    #[test]
+    fn edge_to_same_or_earlier_rank() {
+        for orientation in &[Orientation::LeftToRight, Orientation::TopToBottom] {
+            let mut layout = LayoutConfig::new();
+            layout.orientation = *orientation;
+
+            //
+            // From a vertex in rank 4 back to one in rank 3, below and above
+            // it, and to one in its own rank, right up against the margin.
+            //
+            for (start, channel, end) in &[
+                ((920, 410), 985, (550, 110)),
+                ((920, 110), 985, (550, 410)),
+                ((920, 110), 930, (800, 260)),
+                ((170, 110), 180, (0, 60)),
+            ] {
+                let mut bbox = BoundingBox::new();
+                let path = edge_path(&layout, *start, *channel, *end, None, &mut bbox);
+                let points = path_points(&path);
+                let (start, end) = (layout.point(start.0, start.1), layout.point(end.0, end.1));
+                assert_eq!(points.first(), Some(&(start.0 as i64, start.1 as i64)));
+                assert_eq!(points.last(), Some(&(end.0 as i64, end.1 as i64)));
+
+                //
+                // The route stays within the drawing, and within the box
+                // that was grown to fit it.
+                //
+                let (min_x, min_y, width, height) = bbox.with_margin(0);
+                for (x, y) in points {
+                    assert!(x >= 0 && y >= 0, "{:?} is off the drawing", (x, y));
+                    assert!(x >= min_x && x <= min_x + width, "{} is outside the box", x);
+                    assert!(y >= min_y && y <= min_y + height, "{} is outside the box", y);
+                }
+            }
+        }
+    }
+}
@@ -4,35 +4,62 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 //
 // Copyright 2020 Joyent, Inc.
+// Copyright 2023 MNX Cloud, Inc.
 //
 extern crate env_logger;
 extern crate log;
 
 use log::debug;
 
+extern crate base64;
+extern crate flate2;
 extern crate fs_extra;
+extern crate glob;
 
 extern crate serde;
 extern crate serde_derive;
+extern crate serde_json;
+#[cfg(any(test, feature = "bench"))]
 extern crate serde_xml_rs;
+use serde::Serialize;
+
+extern crate xz2;
+extern crate zstd;
 
 extern crate topo_digraph_xml;
-use topo_digraph_xml::{
-    NvlistXmlArrayElement, TopoDigraphXML, PG_NAME, PG_VALS, PROP_NAME, PROP_VALUE,
-};
+extern crate xml;
 
 extern crate svg;
 use svg::node::element::{
-    Filter, Group, Image, Line, Rectangle, Script};
+    Circle, Element, Filter, Group, Image, Rectangle, Script};
 use svg::Document;
+use svg::Node;
 
+use std::cell::RefCell;
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryInto;
 use std::error::Error;
 use std::fmt;
 use std::fs;
-use std::io::Write;
+use std::io::{self, BufReader, Read, Write};
+use std::str::FromStr;
+
+mod batch;
+mod chassis;
+#[cfg(test)]
+mod fixtures;
+mod layout;
+mod parse;
+mod profile;
+mod serve;
+pub use batch::{batch_inputs, run_batch, BatchResult};
+pub use layout::{LayoutConfig, Orientation};
+pub use profile::ValidationIssue;
+pub use serve::serve;
+
+use chassis::{ChassisManifest, ChassisRegistry};
+use layout::{assign_channels, edge_path, BoundingBox};
 
 //
 // Constants for topo node names in SAS scheme topology
@@ -42,6 +69,61 @@ pub const PORT: &str = "port";
 pub const EXPANDER: &str = "expander";
 pub const TARGET: &str = "target";
 
+//
+// Property names used to look up link state on port vertices
+//
+const MAX_LINK_RATE: &str = "max-link-rate";
+const NEG_LINK_RATE: &str = "negotiated-link-rate";
+
+//
+// The properties that the viewer shows by default for each type of vertex, in
+// the order they are shown.  The properties of a listed type that aren't
+// listed here are only shown when "show all properties" is checked.  Vertices
+// of types that aren't listed have all of their properties shown.
+//
+const DISPLAY_SCHEMA: &[(&str, &[&str])] = &[
+    (
+        INITIATOR,
+        &["hc-fmri", "dev-fmri", "devfs-path", "manufacturer", "model", "location"],
+    ),
+    (
+        PORT,
+        &["sas-port-type", "local-sas-address", "attached-sas-address"],
+    ),
+    (EXPANDER, &["devfs-path", "manufacturer", "model"]),
+    (
+        TARGET,
+        &[
+            "hc-fmri",
+            "dev-fmri",
+            "logical-disk",
+            "manufacturer",
+            "model",
+            "serial-number",
+            "location",
+        ],
+    ),
+];
+
+//
+// Magic numbers at the start of the compressed formats that snapshots can be
+// stored in
+//
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+//
+// Names of the documents making up the webpage
+//
+const SVG_FILE: &str = "sastopo.svg";
+const HTML_FILE: &str = "sastopo2svg.html";
+
+//
+// Placeholder in the HTML template that is replaced with the chassis view
+//
+const CHASSIS_VIEW_MARKER: &str = "<!-- CHASSIS VIEW -->";
+
 #[derive(Debug)]
 struct SimpleError(String);
 
@@ -84,12 +166,31 @@ impl SasDigraphProperty {
     }
 }
 
+//
+// A named group of properties, as they are organized in the XML.  Different
+// groups may define properties with the same name.
+//
+#[derive(Debug)]
+struct PropertyGroup {
+    name: String,
+    properties: Vec<SasDigraphProperty>,
+}
+
+impl PropertyGroup {
+    fn new(name: String) -> PropertyGroup {
+        PropertyGroup {
+            name,
+            properties: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct SasDigraphVertex {
     fmri: String,
     name: String,
     instance: u64,
-    properties: Vec<SasDigraphProperty>,
+    propgroups: Vec<PropertyGroup>,
     geometry: SasGeometry,
     outgoing_edges: Option<Vec<String>>,
 }
@@ -101,17 +202,196 @@ impl SasDigraphVertex {
         instance: u64,
         outgoing_edges: Option<Vec<String>>,
     ) -> SasDigraphVertex {
-        let properties = Vec::new();
+        let propgroups = Vec::new();
         let geometry = SasGeometry::new(0, 0, 0, 0);
         SasDigraphVertex {
             fmri,
             name,
             instance,
-            properties,
+            propgroups,
             geometry,
             outgoing_edges,
         }
     }
+
+    //
+    // Returns the value of the named property, if this vertex has it.  If more
+    // than one property group defines it, the first group wins.
+    //
+    fn get_prop(&self, name: &str) -> Option<&str> {
+        self.propgroups
+            .iter()
+            .flat_map(|pg| pg.properties.iter())
+            .find(|prop| prop.name == name)
+            .map(|prop| prop.value.as_str())
+    }
+
+    //
+    // Port FMRIs carry the range of PHYs that make up the port in their
+    // authority, e.g. "sas://:start-phy=0:end-phy=3/port=...".  Returns
+    // the (start, end) PHY numbers if present.
+    //
+    fn phy_range(&self) -> Option<(u32, u32)> {
+        let mut start = None;
+        let mut end = None;
+
+        let authority = self.fmri.split('/').nth(2)?;
+        for field in authority.split(':') {
+            if let Some(val) = field.strip_prefix("start-phy=") {
+                start = val.parse::<u32>().ok();
+            } else if let Some(val) = field.strip_prefix("end-phy=") {
+                end = val.parse::<u32>().ok();
+            }
+        }
+        match (start, end) {
+            (Some(start), Some(end)) => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    //
+    // Returns the text to label this vertex with in the SVG.  In addition to
+    // the vertex properties, the instance, name and FMRI can be used.
+    //
+    fn label(&self, prop: &str) -> Option<String> {
+        match prop {
+            "instance" => Some(self.instance.to_string()),
+            "name" => Some(self.name.clone()),
+            "fmri" => Some(self.fmri.clone()),
+            _ => self.get_prop(prop).map(|value| value.to_string()),
+        }
+    }
+
+    //
+    // Returns the per-PHY link rate codes stored in the named (array)
+    // property, or an empty Vec if the property isn't present.
+    //
+    fn link_rates(&self, name: &str) -> Vec<u32> {
+        match self.get_prop(name) {
+            Some(value) => value
+                .split(',')
+                .filter_map(|code| code.trim().parse::<u32>().ok())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    //
+    // Summarizes the state of the link(s) behind a port, for annotating the
+    // edges leading from it.  Returns None if this isn't a port or if it has
+    // no link rate properties.
+    //
+    fn link_summary(&self) -> Option<LinkSummary> {
+        if self.name != PORT {
+            return None;
+        }
+        let rates = self.link_rates(NEG_LINK_RATE);
+        if rates.is_empty() {
+            return None;
+        }
+
+        //
+        // Group the PHYs by negotiated rate, fastest first, so that a wide
+        // port with mixed rates is labelled like "3x 12G + 1x 6G".
+        //
+        let mut widths: Vec<(u32, usize)> = Vec::new();
+        for code in &rates {
+            match widths.iter_mut().find(|(c, _)| c == code) {
+                Some((_, width)) => *width += 1,
+                None => widths.push((*code, 1)),
+            }
+        }
+        widths.sort_by_key(|(code, _)| cmp::Reverse(*code));
+
+        let label = widths
+            .iter()
+            .map(|(code, width)| match link_rate_gbps(*code) {
+                Some(rate) => format!("{}x {}G", width, rate),
+                None => format!("{}x down", width),
+            })
+            .collect::<Vec<String>>()
+            .join(" + ");
+        let bandwidth = rates.iter().filter_map(|code| link_rate_gbps(*code)).sum();
+
+        Some(LinkSummary {
+            label,
+            bandwidth,
+            degraded: self.has_degraded_link(),
+        })
+    }
+
+    //
+    // Returns true if this is a port with at least one PHY that either isn't
+    // up or negotiated a rate lower than the maximum it supports.
+    //
+    fn has_degraded_link(&self) -> bool {
+        if self.name != PORT {
+            return false;
+        }
+        let max_rates = self.link_rates(MAX_LINK_RATE);
+        self.link_rates(NEG_LINK_RATE)
+            .iter()
+            .zip(max_rates.iter())
+            .any(|(neg, max)| link_rate_gbps(*neg).is_none() || neg < max)
+    }
+}
+
+//
+// The vertices downstream of an expander, which the viewer can collapse into a
+// single summary node.
+//
+#[derive(Debug)]
+struct Subtree {
+    // short ids of the vertices in the subtree
+    members: Vec<String>,
+    targets: usize,
+    degraded_targets: usize,
+}
+
+//
+// A group of targets that appear to share an enclosure, because they are
+// attached through the same expander(s) and their locations share a prefix.
+//
+#[derive(Debug)]
+struct Enclosure {
+    label: String,
+    // FMRIs of the targets in the enclosure
+    targets: Vec<String>,
+}
+
+//
+// Returns the location with any trailing slot number stripped off, e.g. "Front
+// Disk 12" becomes "Front Disk".
+//
+fn location_prefix(location: &str) -> &str {
+    location
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .trim_end()
+}
+
+#[derive(Debug)]
+struct LinkSummary {
+    // PHY width and negotiated rate, e.g. "4x 12G"
+    label: String,
+    // aggregate negotiated rate of all PHYs, in Gbit/s
+    bandwidth: f64,
+    // true if any PHY negotiated below its maximum rate
+    degraded: bool,
+}
+
+//
+// Converts a SAS negotiated/max link rate code to a rate in Gbit/s.  Codes
+// below 0x8 describe link states rather than rates, so return None for those.
+//
+fn link_rate_gbps(code: u32) -> Option<f64> {
+    match code {
+        0x8 => Some(1.5),
+        0x9 => Some(3.0),
+        0xa => Some(6.0),
+        0xb => Some(12.0),
+        0xc => Some(22.5),
+        _ => None,
+    }
 }
 
 #[derive(Debug)]
@@ -149,91 +429,504 @@ impl SasDigraph {
             initiators,
         }
     }
+
+    //
+    // Returns a hash map of vertex FMRIs to the FMRIs of the vertices that
+    // have an edge pointing at them.
+    //
+    fn incoming_edges(&self) -> HashMap<&str, Vec<&str>> {
+        let mut incoming: HashMap<&str, Vec<&str>> = HashMap::new();
+
+        for vtx in self.vertices.values() {
+            if let Some(edges) = &vtx.outgoing_edges {
+                for edge in edges {
+                    incoming.entry(edge.as_str()).or_default().push(&vtx.fmri);
+                }
+            }
+        }
+        incoming
+    }
+
+    //
+    // Returns the set of vertices that sit on some path from an initiator to
+    // the specified vertex (not including the vertex itself).
+    //
+    fn ancestors<'a>(
+        &'a self,
+        incoming: &HashMap<&'a str, Vec<&'a str>>,
+        fmri: &str,
+    ) -> HashSet<&'a str> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut stack: Vec<&str> = match incoming.get(fmri) {
+            Some(parents) => parents.clone(),
+            None => Vec::new(),
+        };
+
+        while let Some(parent) = stack.pop() {
+            if seen.insert(parent) {
+                if let Some(grandparents) = incoming.get(parent) {
+                    stack.extend(grandparents.iter());
+                }
+            }
+        }
+        seen
+    }
+
+    //
+    // Returns the set of vertices reachable from the specified vertex by
+    // following outgoing edges (not including the vertex itself).
+    //
+    fn descendants(&self, fmri: &str) -> HashSet<&str> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut stack: Vec<&str> = vec![fmri];
+
+        while let Some(parent) = stack.pop() {
+            let edges = match self.vertices.get(parent) {
+                Some(vtx) => vtx.outgoing_edges.iter().flatten(),
+                None => continue,
+            };
+            for child in edges {
+                if seen.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+        seen
+    }
+
+    //
+    // Returns the set of vertices reachable from the initiators without going
+    // through the specified vertex.
+    //
+    fn reachable_avoiding(&self, avoid: &str) -> HashSet<&str> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut stack: Vec<&str> = Vec::new();
+        for fmri in &self.initiators {
+            if fmri != avoid && seen.insert(fmri) {
+                stack.push(fmri);
+            }
+        }
+
+        while let Some(parent) = stack.pop() {
+            let edges = match self.vertices.get(parent) {
+                Some(vtx) => vtx.outgoing_edges.iter().flatten(),
+                None => continue,
+            };
+            for child in edges {
+                if child != avoid && seen.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+        seen
+    }
+
+    //
+    // Returns a hash map of vertex FMRIs to the number of distinct paths from
+    // an initiator to that vertex.  For targets this is the number of paths
+    // the OS has to the disk.
+    //
+    fn path_counts(&self) -> HashMap<String, u64> {
+        //
+        // Walks depth first from the specified vertex, recording the edges
+        // that lead back to a vertex still on the walk, and appending each
+        // vertex to the order once everything below it has been visited.
+        //
+        fn visit<'a>(
+            digraph: &'a SasDigraph,
+            fmri: &'a str,
+            on_walk: &mut HashSet<&'a str>,
+            visited: &mut HashSet<&'a str>,
+            back_edges: &mut HashSet<(&'a str, &'a str)>,
+            order: &mut Vec<&'a str>,
+        ) {
+            visited.insert(fmri);
+            on_walk.insert(fmri);
+            if let Some(vtx) = digraph.vertices.get(fmri) {
+                for child in vtx.outgoing_edges.iter().flatten() {
+                    if on_walk.contains(child.as_str()) {
+                        back_edges.insert((fmri, child));
+                    } else if !visited.contains(child.as_str()) {
+                        visit(digraph, child, on_walk, visited, back_edges, order);
+                    }
+                }
+            }
+            on_walk.remove(fmri);
+            order.push(fmri);
+        }
+
+        //
+        // The SAS digraph should be acyclic, but guard against malformed
+        // input: the edges that close a cycle, as found by walking from the
+        // initiators in order, are left out, so paths that go round a cycle
+        // aren't counted and the counts don't depend on hash map order.
+        //
+        let mut initiators: Vec<&str> = self.initiators.iter().map(|i| i.as_str()).collect();
+        initiators.sort_unstable();
+        let mut on_walk = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut back_edges = HashSet::new();
+        let mut order = Vec::new();
+        for fmri in &initiators {
+            if !visited.contains(fmri) {
+                visit(self, fmri, &mut on_walk, &mut visited, &mut back_edges, &mut order);
+            }
+        }
+
+        //
+        // Without those edges, the reverse of the order the walk finished
+        // the vertices in has every vertex after all of its parents, so the
+        // paths to each vertex can be passed on to its children in turn.
+        //
+        let mut counts: HashMap<String, u64> =
+            self.vertices.keys().map(|fmri| (fmri.clone(), 0)).collect();
+        for fmri in order.iter().rev() {
+            let vtx = match self.vertices.get(*fmri) {
+                Some(vtx) => vtx,
+                None => continue,
+            };
+            let mut n = counts[*fmri];
+            if initiators.binary_search(fmri).is_ok() {
+                n += 1;
+                counts.insert(fmri.to_string(), n);
+            }
+            for child in vtx.outgoing_edges.iter().flatten() {
+                if back_edges.contains(&(*fmri, child.as_str())) {
+                    continue;
+                }
+                if let Some(count) = counts.get_mut(child) {
+                    *count += n;
+                }
+            }
+        }
+        counts
+    }
+
+    //
+    // Returns the set of vertices that are affected by a degraded link.  A
+    // vertex is degraded if it is, or is directly attached to, a port with a
+    // degraded link, or if such a port sits on any path from an initiator to
+    // the vertex.
+    //
+    fn degraded_vertices(&self) -> HashSet<&str> {
+        let ports: HashSet<&str> = self
+            .vertices
+            .values()
+            .filter(|vtx| vtx.has_degraded_link())
+            .map(|vtx| vtx.fmri.as_str())
+            .collect();
+
+        //
+        // The degraded ports and the vertices attached to them from upstream
+        //
+        let mut degraded: HashSet<&str> = self
+            .vertices
+            .values()
+            .filter(|vtx| {
+                ports.contains(vtx.fmri.as_str())
+                    || vtx.outgoing_edges.iter().flatten().any(|fmri| ports.contains(fmri.as_str()))
+            })
+            .map(|vtx| vtx.fmri.as_str())
+            .collect();
+
+        //
+        // Everything downstream of them, found in a single walk from all of
+        // the degraded ports at once
+        //
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut stack: Vec<&str> = ports.into_iter().collect();
+        while let Some(parent) = stack.pop() {
+            let edges = match self.vertices.get(parent) {
+                Some(vtx) => vtx.outgoing_edges.iter().flatten(),
+                None => continue,
+            };
+            for child in edges {
+                if seen.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+        degraded.extend(seen.into_iter().filter(|fmri| self.vertices.contains_key(*fmri)));
+        degraded
+    }
+
+    //
+    // Returns the expanders that the specified vertex is attached to, either
+    // directly or through ports, but not through another expander.
+    //
+    fn nearest_expanders<'a>(
+        &'a self,
+        incoming: &HashMap<&'a str, Vec<&'a str>>,
+        fmri: &str,
+    ) -> Vec<&'a str> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut found: Vec<&str> = Vec::new();
+        let mut stack: Vec<&str> = match incoming.get(fmri) {
+            Some(parents) => parents.clone(),
+            None => Vec::new(),
+        };
+
+        while let Some(parent) = stack.pop() {
+            if !seen.insert(parent) {
+                continue;
+            }
+            if self.vertices[parent].name == EXPANDER {
+                found.push(parent);
+            } else if let Some(grandparents) = incoming.get(parent) {
+                stack.extend(grandparents.iter());
+            }
+        }
+        found.sort_unstable();
+        found
+    }
+
+    //
+    // Groups the targets attached through expanders into enclosures.  Targets
+    // attached through the same set of nearest expanders, whose locations
+    // share a prefix, are assumed to be in the same enclosure.  Enclosures are
+    // labelled with the location prefix, numbered if it isn't unique.
+    //
+    fn enclosures(&self) -> Vec<Enclosure> {
+        // enclosures are keyed by their nearest expanders and location prefix
+        type Key<'a> = (Vec<&'a str>, &'a str);
+
+        let incoming = self.incoming_edges();
+        let mut groups: HashMap<Key, Vec<String>> = HashMap::new();
+
+        for vtx in self.vertices.values().filter(|vtx| vtx.name == TARGET) {
+            let expanders = self.nearest_expanders(&incoming, &vtx.fmri);
+            if expanders.is_empty() {
+                continue;
+            }
+            let prefix = location_prefix(vtx.get_prop("location").unwrap_or(""));
+            groups.entry((expanders, prefix)).or_default().push(vtx.fmri.clone());
+        }
+
+        let mut groups: Vec<(Key, Vec<String>)> = groups.into_iter().collect();
+        groups.sort();
+
+        let mut prefix_counts: HashMap<&str, usize> = HashMap::new();
+        for ((_, prefix), _) in &groups {
+            *prefix_counts.entry(prefix).or_default() += 1;
+        }
+
+        let mut prefix_seen: HashMap<&str, usize> = HashMap::new();
+        let mut enclosures = Vec::new();
+        for ((_, prefix), mut targets) in groups {
+            let name = if prefix.is_empty() { "Enclosure" } else { prefix };
+            let label = match prefix_counts[prefix] {
+                1 => name.to_string(),
+                _ => {
+                    let n = prefix_seen.entry(prefix).or_default();
+                    *n += 1;
+                    format!("{} {}", name, n)
+                }
+            };
+            targets.sort();
+            enclosures.push(Enclosure { label, targets });
+        }
+        enclosures
+    }
+
+    //
+    // Returns a map of expander FMRIs to the subtree downstream of them, for
+    // every expander that has targets attached only through it (directly or
+    // through cascaded expanders).  Vertices that can also be reached without
+    // going through the expander, such as targets multipathed through another
+    // expander, stay visible when it's collapsed, so they aren't part of its
+    // subtree.  Vertices are identified by the short ids in vertex_ids.  The
+    // map is ordered by FMRI so that the SVG comes out the same every time.
+    //
+    fn expander_subtrees(&self, vertex_ids: &HashMap<String, String>) -> BTreeMap<String, Subtree> {
+        let degraded = self.degraded_vertices();
+        let mut subtrees = BTreeMap::new();
+
+        for vtx in self.vertices.values().filter(|vtx| vtx.name == EXPANDER) {
+            let elsewhere = self.reachable_avoiding(&vtx.fmri);
+            let descendants: Vec<&str> = self
+                .descendants(&vtx.fmri)
+                .into_iter()
+                .filter(|fmri| !elsewhere.contains(fmri))
+                .collect();
+            let targets: Vec<&str> = descendants
+                .iter()
+                .filter(|fmri| matches!(self.vertices.get(**fmri), Some(vtx) if vtx.name == TARGET))
+                .copied()
+                .collect();
+            if targets.is_empty() {
+                continue;
+            }
+
+            let mut members: Vec<String> = descendants
+                .iter()
+                .filter_map(|fmri| vertex_ids.get(*fmri).cloned())
+                .collect();
+            members.sort();
+            subtrees.insert(
+                vtx.fmri.clone(),
+                Subtree {
+                    members,
+                    targets: targets.len(),
+                    degraded_targets: targets.iter().filter(|fmri| degraded.contains(*fmri)).count(),
+                },
+            );
+        }
+        subtrees
+    }
 }
 
-#[derive(Debug)]
+//
+// An overview of the health of a host's SAS fabric, for the fleet index page.
+//
+#[derive(Clone, Debug)]
+pub struct HostSummary {
+    pub product_id: String,
+    pub nodename: String,
+    pub os_version: String,
+    pub timestamp: String,
+    // number of targets
+    pub disks: usize,
+    // number of ports with a degraded link
+    pub degraded_ports: usize,
+    // number of targets with at most one path from an initiator
+    pub single_pathed_disks: usize,
+}
+
+impl HostSummary {
+    fn new(digraph: &SasDigraph) -> HostSummary {
+        let path_counts = digraph.path_counts();
+        let targets: Vec<&SasDigraphVertex> = digraph
+            .vertices
+            .values()
+            .filter(|vtx| vtx.name == TARGET)
+            .collect();
+
+        HostSummary {
+            product_id: digraph.product_id.clone(),
+            nodename: digraph.nodename.clone(),
+            os_version: digraph.os_version.clone(),
+            timestamp: digraph.timestamp.clone(),
+            disks: targets.len(),
+            degraded_ports: digraph
+                .vertices
+                .values()
+                .filter(|vtx| vtx.has_degraded_link())
+                .count(),
+            single_pathed_disks: targets
+                .iter()
+                .filter(|vtx| path_counts.get(&vtx.fmri).copied().unwrap_or(0) <= 1)
+                .count(),
+        }
+    }
+}
+
+//
+// The single-document formats that can be written to stdout instead of the
+// webpage.
+//
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Svg,
+    Json,
+    Dot,
+    Html,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "svg" => Ok(OutputFormat::Svg),
+            "json" => Ok(OutputFormat::Json),
+            "dot" => Ok(OutputFormat::Dot),
+            "html" => Ok(OutputFormat::Html),
+            _ => Err(format!("invalid output format: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Config {
     pub outdir: String,
+    // path to the output of sastopo -x, or "-" for stdin
     pub xml_path: String,
+    // hashmap of vertex names to the property used to label them
+    pub labels: HashMap<String, String>,
+    // labels longer than this many characters are truncated
+    pub label_length: usize,
+    pub layout: LayoutConfig,
+    // expanders with more than this many targets downstream start collapsed
+    pub collapse_threshold: Option<usize>,
+    // directory holding the vertex icons and chassis images, if not the
+    // assets directory installed alongside the executable
+    pub asset_dir: Option<String>,
+    // write a single document in this format to stdout, rather than the
+    // webpage to outdir
+    pub format: Option<OutputFormat>,
+    // URL, relative to the webpage, of an assets directory that it shares
+    // with other webpages, rather than copying the images to outdir
+    pub shared_assets: Option<String>,
 }
 
 impl Config {
     pub fn new(outdir: String, xml_path: String) -> Config {
+        let mut labels = HashMap::new();
+        labels.insert(INITIATOR.to_string(), "instance".to_string());
+        labels.insert(PORT.to_string(), "local-sas-address".to_string());
+        labels.insert(EXPANDER.to_string(), "model".to_string());
+        labels.insert(TARGET.to_string(), "location".to_string());
+
         Config {
             outdir,
             xml_path,
+            labels,
+            label_length: 18,
+            layout: LayoutConfig::new(),
+            collapse_threshold: None,
+            asset_dir: None,
+            format: None,
+            shared_assets: None,
         }
     }
 }
 
 //
-// Parse an NvlistXmlArrayElement representing a topo property, extract the
-// prop name and value (as a string) and return a SasDigraphProperty.
+// Vertices that can be reached by more than one path (e.g. multipathed
+// targets) are only placed in the column of the first path that reaches them,
+// so that each vertex is drawn once and all of its incoming edges lead to it.
+// Returns the deepest column that a vertex was placed in.
 //
-fn parse_prop(nvl: &NvlistXmlArrayElement) -> Result<SasDigraphProperty, Box<dyn Error>> {
-    let mut propname: Option<String> = None;
-    let mut propval: Option<String> = None;
-
-    if nvl.nvpairs.is_some() {
-        for nvpair in nvl.nvpairs.as_ref().unwrap() {
-            match nvpair.name.as_ref().unwrap().as_ref() {
-                PROP_NAME => {
-                    propname = Some(nvpair.value.as_ref().unwrap().clone());
-                }
-                PROP_VALUE => {
-                    if nvpair.nvpair_elements.is_some() {
-                        //
-                        // If nvpair_elements is something then this is an array
-                        // type in which case we iterate through the child nvpairs
-                        // and create a string with all the array values,
-                        // delimited by a comma.
-                        //
-                        let mut valarr = Vec::new();
-                        for elem in nvpair.nvpair_elements.as_ref().unwrap() {
-                            valarr.push(elem.value.as_ref().unwrap().clone());
-                        }
-                        propval = Some(valarr.join(","));
-                    } else {
-                        propval = Some(nvpair.value.as_ref().unwrap().clone());
-                    }
-                }
-                _ => {}
-            }
-        }
-    }
-
-    if let (Some(name), Some(val)) = (propname, propval) {
-        Ok(SasDigraphProperty::new(name, val))
-    } else {
-        Err(Box::new(SimpleError(format!(
-            "malformed property value nvlist: {:?}",
-            nvl
-        ))))
-    }
-}
-
 fn visit_vertex(
     vertices: &HashMap<String, SasDigraphVertex>,
     vtx: &SasDigraphVertex,
     column_hash: &mut HashMap<u32, Vec<String>>,
+    placed: &mut HashSet<String>,
     depth: u32,
 ) -> Result<u32, Box<dyn Error>> {
+    //
+    // A vertex that has already been placed isn't placed in this column, so
+    // the deepest column is still the one its parent is in.
+    //
+    if !placed.insert(vtx.fmri.clone()) {
+        return Ok(depth);
+    }
     let mut max_depth = depth + 1;
 
     column_hash
         .entry(max_depth)
-        .or_insert_with(Vec::new)
+        .or_default()
         .push(vtx.fmri.clone());
 
-    if vtx.outgoing_edges.is_some() {
-        for edge in vtx.outgoing_edges.as_ref().unwrap() {
+    if let Some(outgoing_edges) = &vtx.outgoing_edges {
+        for edge in outgoing_edges {
             let next_vtx = match vertices.get(&edge.to_string()) {
                 Some(entry) => entry,
                 None => {
                     return Err(Box::new(SimpleError("failed to lookup vertex".to_string())));
                 }
             };
-            let rc = visit_vertex(vertices, next_vtx, column_hash, depth + 1)?;
+            let rc = visit_vertex(vertices, next_vtx, column_hash, placed, depth + 1)?;
             if rc > max_depth {
                 max_depth = rc;
             }
@@ -243,42 +936,443 @@ fn visit_vertex(
 }
 
 //
-// Generates an SVG representation of the directed graph and save it to a file.
+// Creates a <title> element, which browsers display as a tooltip for its
+// parent element.
 //
-fn build_svg(config: &Config, digraph: &mut SasDigraph) -> Result<(), Box<dyn Error>> {
-    let mut max_depth: u32 = 0;
-    let mut max_height: usize = 0;
-    let mut column_hash: HashMap<u32, Vec<String>> = HashMap::new();
-    let depth: u32 = 0;
+fn title(text: &str) -> Element {
+    let mut title = Element::new("title");
+    title.append(svg::node::Text::new(escape(text)));
+    title
+}
 
-    //
-    // First we create a hidden element that we can attach the host information
-    // properties to.  The JS code will reference those to populate the Host
-    // Information table,
-    //
-    let hostinfo = Rectangle::new()
-        .set("x", 1)
-        .set("y", 1)
-        .set("width", 1)
-        .set("height", 1)
-        .set("visibility", "hidden")
-        .set("id", "hostprops")
-        .set("product-id", digraph.product_id.clone())
-        .set("nodename", digraph.nodename.clone())
-        .set("os-version", digraph.os_version.clone())
-        .set("timestamp", digraph.timestamp.clone());
+//
+// Escapes the characters that are special in XML text and attribute values.
+// The svg crate writes both out verbatim, so everything that comes from the
+// topology snapshot has to be passed through this before it goes into the
+// document, otherwise a crafted property value could inject markup (and
+// script) into the viewer.
+//
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
 
-    //
-    // Next we iterate over all of the paths through the digraph starting from
-    // the initiator vertices.  There are two purposes here:
-    //
-    // The first is to calculate the maximum depth (width) of the graph.
-    // The second is to create a hash map of vertex FMRIs, hashed by their
-    // depth.
-    //
-    // We'll iterate through that hash to determine the maximum height of the
-    // graph, and then again when we construct the SVG elements.
-    //
+//
+// Creates a <text> element displaying the specified label centered at the
+// specified position.  Labels longer than max_len characters are truncated,
+// with the full label available as a tooltip.
+//
+fn label(text: &str, max_len: usize, x: u32, y: u32) -> Element {
+    let mut content: String = text.chars().take(max_len).collect();
+    if content.len() < text.len() {
+        content.pop();
+        content.push('\u{2026}');
+    }
+
+    let mut label = Element::new("text");
+    label.assign("x", x);
+    label.assign("y", y);
+    label.assign("class", "label");
+    label.assign("text-anchor", "middle");
+    label.assign("font-family", "Arial, Helvetica, sans-serif");
+    label.assign("font-size", 12);
+    label.append(svg::node::Text::new(escape(&content)));
+    label.append(title(text));
+    label
+}
+
+//
+// Creates a labelled box enclosing the specified target vertices, and the
+// labels below them, which must be next to each other in the same rank so
+// that nothing else ends up in the box.  disks is the number of disks in the
+// whole enclosure.  members are the short ids of the targets, so that the
+// viewer can hide the box when they're all collapsed.  The box is added to
+// the bounding box.
+//
+fn enclosure_box(
+    name: &str,
+    disks: usize,
+    targets: &[&SasDigraphVertex],
+    members: &[String],
+    bbox: &mut BoundingBox,
+) -> Group {
+    const PADDING: u32 = 4;
+
+    let min_x = targets.iter().map(|vtx| vtx.geometry.x).min().unwrap_or(0);
+    let min_y = targets.iter().map(|vtx| vtx.geometry.y).min().unwrap_or(0);
+    let max_x = targets.iter().map(|vtx| vtx.geometry.x + vtx.geometry.width).max().unwrap_or(0);
+    let max_y = targets.iter().map(|vtx| vtx.geometry.y + vtx.geometry.height).max().unwrap_or(0);
+
+    let x = min_x.saturating_sub(PADDING);
+    let y = min_y.saturating_sub(PADDING);
+    let width = (max_x - min_x) + (2 * PADDING);
+    let height = (max_y - min_y) + layout::LABEL_SPACE + (2 * PADDING);
+    bbox.include(x.into(), y.into(), width.into(), height.into());
+
+    let mut text = Element::new("text");
+    text.assign("x", x + 6);
+    text.assign("y", y + 14);
+    text.assign("class", "enclosure-label");
+    text.assign("font-family", "Arial, Helvetica, sans-serif");
+    text.assign("font-size", 11);
+    text.assign("fill", "#606060");
+    text.append(svg::node::Text::new(escape(name)));
+
+    Group::new()
+        .set("class", "enclosure")
+        .set("data-members", members.join(" "))
+        .add(
+            Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("rx", 10)
+                .set("width", width)
+                .set("height", height)
+                .set("fill", "#f4f4fa")
+                .set("stroke", "#8080a0")
+                .set("stroke-width", 1),
+        )
+        .add(text)
+        .add(title(&format!("{} ({} disks)", name, disks)))
+}
+
+//
+// Creates the button drawn at the top right corner (x, y) of an expander that
+// collapses or expands the subtree downstream of it.
+//
+fn subtree_toggle(expander_id: &str, x: u32, y: u32, collapsed: bool) -> Group {
+    let mut sign = Element::new("text");
+    sign.assign("x", x - 9);
+    sign.assign("y", y + 14);
+    sign.assign("text-anchor", "middle");
+    sign.assign("font-family", "Arial, Helvetica, sans-serif");
+    sign.assign("font-size", 14);
+    sign.append(svg::node::Text::new(if collapsed { "+" } else { "\u{2212}" }));
+
+    Group::new()
+        .set("class", "toggle")
+        .set("data-expander", expander_id)
+        .set("onclick", "toggleSubtree(evt)")
+        .set("cursor", "pointer")
+        .add(
+            Circle::new()
+                .set("cx", x - 9)
+                .set("cy", y + 9)
+                .set("r", 8)
+                .set("fill", "white")
+                .set("stroke", "black"),
+        )
+        .add(sign)
+        .add(title("Collapse or expand the devices behind this expander"))
+}
+
+//
+// Creates the summary node that stands in for the subtree downstream of an
+// expander while it is collapsed.  It is placed in the rank after the
+// expander, connected to it by an edge, and shows the number of disks in the
+// subtree and how many of them are affected by a degraded link.  Clicking it
+// expands the subtree again.
+//
+fn subtree_summary(
+    layout: &LayoutConfig,
+    expander: &SasDigraphVertex,
+    expander_id: &str,
+    subtree: &Subtree,
+    collapsed: bool,
+    bbox: &mut BoundingBox,
+) -> Group {
+    const SUMMARY_HEIGHT: u32 = 40;
+
+    let text = format!("{} disks, {} degraded", subtree.targets, subtree.degraded_targets);
+    let width = cmp::max(layout.node_size, ((text.len() as u32 * 11 * 6) / 10) + 16);
+
+    //
+    // Work out where the summary goes along the rank and sibling axes, then
+    // map that to x/y coordinates.
+    //
+    let start = layout.edge_start(expander);
+    let rank = layout.axes(expander.geometry.x, expander.geometry.y).0 + layout.rank_pitch();
+    let (x, y) = match layout.orientation {
+        Orientation::LeftToRight => {
+            layout.point(rank, start.1.saturating_sub(SUMMARY_HEIGHT / 2))
+        }
+        Orientation::TopToBottom => layout.point(rank, start.1.saturating_sub(width / 2)),
+    };
+    bbox.include(x.into(), y.into(), width.into(), SUMMARY_HEIGHT.into());
+
+    let edge = edge_path(layout, start, start.0, (rank, start.1), None, bbox);
+
+    let mut label = Element::new("text");
+    label.assign("x", x + (width / 2));
+    label.assign("y", y + (SUMMARY_HEIGHT / 2) + 4);
+    label.assign("text-anchor", "middle");
+    label.assign("font-family", "Arial, Helvetica, sans-serif");
+    label.assign("font-size", 11);
+    label.append(svg::node::Text::new(text));
+
+    let mut group = Group::new()
+        .set("class", "summary")
+        .set("data-expander", expander_id)
+        .set("onclick", "toggleSubtree(evt)")
+        .set("cursor", "pointer")
+        .add(edge)
+        .add(
+            Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("rx", 6)
+                .set("width", width)
+                .set("height", SUMMARY_HEIGHT)
+                .set("fill", if subtree.degraded_targets > 0 { "#f8d0d0" } else { "#e0f0e0" })
+                .set("stroke", "black"),
+        )
+        .add(label)
+        .add(title("Click to expand"));
+    if !collapsed {
+        group = group.set("display", "none");
+    }
+    group
+}
+
+//
+// Generates an SVG view of the chassis, made up of the pictures of each side
+// of it with a clickable rectangle drawn over every slot.  Slots are styled
+// (by the stylesheet in the HTML page) according to whether they are empty,
+// occupied by a device affected by a degraded link, or occupied and healthy.
+//
+fn build_chassis_view(
+    manifest: &ChassisManifest,
+    digraph: &SasDigraph,
+    assets: &Assets,
+) -> Result<Document, Box<dyn Error>> {
+    let degraded = digraph.degraded_vertices();
+    let mut slot_state: HashMap<&str, &str> = HashMap::new();
+    for vtx in digraph.vertices.values() {
+        if let Some(location) = vtx.get_prop("location") {
+            let state = slot_state.entry(location).or_insert("ok");
+            if degraded.contains(vtx.fmri.as_str()) {
+                *state = "degraded";
+            }
+        }
+    }
+
+    let mut view_y = 0;
+    let mut view_width = 0;
+    let mut document = Document::new().set("id", "chassis");
+
+    for view in &manifest.views {
+        let img = Image::new()
+            .set("href", escape(&assets.href(&format!("{}/{}", manifest.dir, view.image))?))
+            .set("x", 0)
+            .set("y", view_y)
+            .set("width", view.width)
+            .set("height", view.height);
+        document = document.add(img);
+
+        for slot in manifest.view_slots(view) {
+            let state = match slot_state.get(slot.location.as_str()) {
+                Some(state) => state,
+                None => "empty",
+            };
+            let title = title(&format!("{} ({})", slot.location, state));
+            let bay = Rectangle::new()
+                .set("class", format!("bay bay-{}", state))
+                .set("data-location", escape(&slot.location))
+                .set("x", slot.x)
+                .set("y", view_y + slot.y)
+                .set("width", slot.width)
+                .set("height", slot.height)
+                .add(title);
+            document = document.add(bay);
+        }
+
+        view_y += view.height;
+        view_width = cmp::max(view_width, view.width);
+    }
+
+    Ok(document
+        .set("width", view_width)
+        .set("height", view_y)
+        .set("viewBox", (0, 0, view_width, view_y)))
+}
+
+//
+// The host and vertex data that the viewer displays, which is embedded in the
+// SVG as JSON.  Vertices are keyed by FMRI.
+//
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ViewerHost<'a> {
+    product_id: &'a str,
+    nodename: &'a str,
+    os_version: &'a str,
+    timestamp: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct ViewerPropGroup<'a> {
+    name: &'a str,
+    properties: BTreeMap<&'a str, &'a str>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ViewerVertex<'a> {
+    name: &'a str,
+    instance: u64,
+    propgroups: Vec<ViewerPropGroup<'a>>,
+    outgoing_edges: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+struct ViewerData<'a> {
+    host: ViewerHost<'a>,
+    schema: BTreeMap<&'a str, &'a [&'a str]>,
+    vertices: BTreeMap<&'a str, ViewerVertex<'a>>,
+}
+
+impl<'a> ViewerData<'a> {
+    fn new(digraph: &'a SasDigraph) -> ViewerData<'a> {
+        let vertices = digraph
+            .vertices
+            .values()
+            .map(|vtx| {
+                let propgroups = vtx
+                    .propgroups
+                    .iter()
+                    .map(|pg| ViewerPropGroup {
+                        name: &pg.name,
+                        properties: pg
+                            .properties
+                            .iter()
+                            .map(|prop| (prop.name.as_str(), prop.value.as_str()))
+                            .collect(),
+                    })
+                    .collect();
+                let data = ViewerVertex {
+                    name: &vtx.name,
+                    instance: vtx.instance,
+                    propgroups,
+                    outgoing_edges: vtx.outgoing_edges.as_deref().unwrap_or(&[]),
+                };
+                (vtx.fmri.as_str(), data)
+            })
+            .collect();
+
+        ViewerData {
+            host: ViewerHost {
+                product_id: &digraph.product_id,
+                nodename: &digraph.nodename,
+                os_version: &digraph.os_version,
+                timestamp: &digraph.timestamp,
+            },
+            schema: DISPLAY_SCHEMA.iter().cloned().collect(),
+            vertices,
+        }
+    }
+}
+
+//
+// The images referenced by the generated documents.  The webpage links to
+// copies of them in its assets directory, but a single document written to
+// stdout has to stand alone, so they're embedded in it as data URIs.
+//
+struct Assets {
+    // directory the images are copied or embedded from
+    src_dir: String,
+    // URL of the directory the images are linked to from
+    url: String,
+    embed: bool,
+    // hashmap of data URIs of the images embedded so far, hashed by path
+    embedded: RefCell<HashMap<String, String>>,
+}
+
+impl Assets {
+    fn new(config: &Config, embed: bool) -> Result<Assets, Box<dyn Error>> {
+        //
+        // The images live in the assets directory that is installed
+        // alongside the executable, unless told otherwise.
+        //
+        let src_dir = match &config.asset_dir {
+            Some(dir) => dir.clone(),
+            None => {
+                let src_dir_path = std::env::current_exe()?;
+                let src_dir = match src_dir_path.parent() {
+                    Some (path) => path.to_str().unwrap(),
+                    None => "/"
+                };
+                format!("{}/assets", src_dir)
+            }
+        };
+
+        Ok(Assets {
+            src_dir,
+            url: config.shared_assets.clone().unwrap_or_else(|| "assets".to_string()),
+            embed,
+            embedded: RefCell::new(HashMap::new()),
+        })
+    }
+
+    //
+    // Returns the URI to reference the image at the specified path (relative
+    // to the assets directory) by.
+    //
+    fn href(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        if !self.embed {
+            return Ok(format!("{}/{}", self.url, path));
+        }
+        if let Some(uri) = self.embedded.borrow().get(path) {
+            return Ok(uri.clone());
+        }
+
+        let mime = match path.rsplit('.').next() {
+            Some("png") => "image/png",
+            Some("jpg") | Some("jpeg") => "image/jpeg",
+            Some("svg") => "image/svg+xml",
+            _ => "application/octet-stream",
+        };
+        let data = fs::read(format!("{}/{}", self.src_dir, path))?;
+        let uri = format!("data:{};base64,{}", mime, base64::encode(&data));
+        self.embedded.borrow_mut().insert(path.to_string(), uri.clone());
+        Ok(uri)
+    }
+}
+
+//
+// Generates an SVG representation of the directed graph.
+//
+fn build_svg(
+    config: &Config,
+    digraph: &mut SasDigraph,
+    assets: &Assets,
+) -> Result<Document, Box<dyn Error>> {
+    let mut max_depth: u32 = 0;
+    let mut max_height: usize = 0;
+    let mut column_hash: HashMap<u32, Vec<String>> = HashMap::new();
+    let mut placed: HashSet<String> = HashSet::new();
+    let depth: u32 = 0;
+
+    //
+    // First we iterate over all of the paths through the digraph starting from
+    // the initiator vertices.  There are two purposes here:
+    //
+    // The first is to calculate the maximum depth (width) of the graph.
+    // The second is to create a hash map of vertex FMRIs, hashed by their
+    // depth.
+    //
+    // We'll iterate through that hash to determine the maximum height of the
+    // graph, and then again when we construct the SVG elements.
+    //
     // Based on the maximum depth and height, we'll divide the document into a
     // grid and use that to determine the size and placement of the various SVG
     // elements.
@@ -292,7 +1386,7 @@ fn build_svg(config: &Config, digraph: &mut SasDigraph) -> Result<(), Box<dyn Er
             }
         };
 
-        let rc = visit_vertex(&digraph.vertices, vtx, &mut column_hash, depth)?;
+        let rc = visit_vertex(&digraph.vertices, vtx, &mut column_hash, &mut placed, depth)?;
         if rc > max_depth {
             max_depth = rc;
         }
@@ -324,15 +1418,107 @@ fn build_svg(config: &Config, digraph: &mut SasDigraph) -> Result<(), Box<dyn Er
         .set("id", "linear")
         .add(filter_matrix);
 
+    //
+    // Styles for the viewer: search results are highlighted and everything
+    // else is dimmed, the paths through the selected vertex are highlighted
+    // and the vertex with the keyboard focus is outlined.
+    //
+    let mut style = Element::new("style");
+    style.append(svg::node::Text::new(
+        "svg.searching g.vertex, svg.searching .edge, svg.searching .link-label \
+         { opacity: 0.25; } \
+         svg.searching g.vertex.match { opacity: 1; } \
+         g.match image { filter: drop-shadow(0 0 6px #FF9900); } \
+         g.match.current image { filter: url(#linear) drop-shadow(0 0 8px #FF6600); } \
+         .edge.on-path { stroke: #0066FF; } \
+         g.vertex.on-path image { filter: drop-shadow(0 0 5px #0066FF); } \
+         g.vertex.on-path.match image { filter: drop-shadow(0 0 6px #FF9900); } \
+         g.vertex.selected image { filter: url(#linear) drop-shadow(0 0 5px #0066FF); } \
+         g.vertex:focus { outline: none; } \
+         g.vertex:focus-visible { outline: 2px dashed #0066FF; }",
+    ));
+
+    let orientation = match config.layout.orientation {
+        Orientation::LeftToRight => "lr",
+        Orientation::TopToBottom => "tb",
+    };
     let mut document = Document::new()
         .set("overflow", "scroll")
-        .set("viewbox", (0, 0, (100 * max_depth), (250 * max_height)))
+        .set("data-orientation", orientation)
         .add(on_click)
         .add(filter)
-        .add(hostinfo);
+        .add(style);
+
+    //
+    // Everything making up the graph itself goes in a single group, which the
+    // viewer's minimap draws a copy of.
+    //
+    let mut graph = Group::new().set("id", "graph");
 
-    let vtx_width = 120;
-    let vtx_height = 120;
+    let layout = &config.layout;
+    let vtx_width = layout.node_size;
+    let vtx_height = layout.node_size;
+    let mut bbox = BoundingBox::new();
+
+    //
+    // Give each vertex a short id, which the elements that refer to it (edges
+    // and expander subtrees) use rather than its FMRI.
+    //
+    let mut vertex_ids: HashMap<String, String> = HashMap::new();
+    for depth in 1..=max_depth {
+        for fmri in &column_hash[&depth] {
+            let id = format!("v{}", vertex_ids.len());
+            vertex_ids.insert(fmri.clone(), id);
+        }
+    }
+
+    //
+    // The subtree downstream of each expander can be collapsed in the viewer.
+    // Expanders with more targets than the collapse threshold start collapsed.
+    //
+    let subtrees = digraph.expander_subtrees(&vertex_ids);
+    let collapsed: HashSet<&str> = subtrees
+        .iter()
+        .filter(|(_, subtree)| match config.collapse_threshold {
+            Some(threshold) => subtree.targets > threshold,
+            None => false,
+        })
+        .map(|(fmri, _)| fmri.as_str())
+        .collect();
+
+    let incoming = digraph.incoming_edges();
+    let id_list = |fmris: HashSet<&str>| -> String {
+        let mut ids: Vec<&str> = fmris
+            .into_iter()
+            .filter_map(|fmri| vertex_ids.get(fmri).map(|id| id.as_str()))
+            .collect();
+        // ids are numbered in the order the vertices are placed
+        ids.sort_unstable_by_key(|id| id[1..].parse::<usize>().unwrap_or(0));
+        ids.join(" ")
+    };
+
+    //
+    // The viewer needs the parents and children of each vertex, both to
+    // navigate it with the keyboard and to highlight every initiator to
+    // target path through the selected vertex, which it finds by following
+    // them up and down from there.
+    //
+    let adjacency: HashMap<String, (String, String)> = vertex_ids
+        .keys()
+        .map(|fmri| {
+            let parents = match incoming.get(fmri.as_str()) {
+                Some(parents) => id_list(parents.iter().copied().collect()),
+                None => String::new(),
+            };
+            let children = match &digraph.vertices[fmri].outgoing_edges {
+                Some(edges) => id_list(edges.iter().map(|edge| edge.as_str()).collect()),
+                None => String::new(),
+            };
+            (fmri.clone(), (parents, children))
+        })
+        .collect();
+
+    let mut vertex_groups: Vec<Group> = Vec::new();
 
     //
     // Generate the SVG elements for all the vertices.
@@ -344,15 +1530,12 @@ fn build_svg(config: &Config, digraph: &mut SasDigraph) -> Result<(), Box<dyn Er
             let vtx_fmri: String = vertices[index].to_string();
             let vtx = digraph.vertices.get_mut(&vtx_fmri).unwrap();
 
-            let x_margin = 50;
-            let y_margin = 10;
-            let x = ((depth - 1) * 250) + x_margin;
-
-            let y_factor: u32 = match height {
-                1 => 1,
-                _ => (max_height / vertices.len()).try_into().unwrap(),
-            };
-            let y = ((height - 1) * 150 * y_factor) + y_margin;
+            let (x, y) = layout.place(
+                depth,
+                index.try_into().unwrap(),
+                vertices.len().try_into().unwrap(),
+                max_height.try_into().unwrap(),
+            );
 
             debug!(
                 "VERTEX: fmri: {}, depth: {}, height: {}, x: {}, y: {}",
@@ -360,247 +1543,920 @@ fn build_svg(config: &Config, digraph: &mut SasDigraph) -> Result<(), Box<dyn Er
             );
 
             let imguri = match vtx.name.as_ref() {
-                INITIATOR => "assets/icons/initiator.png",
-                PORT => "assets/icons/port.png",
-                EXPANDER => "assets/icons/expander.png",
-                TARGET => "assets/icons/target.png",
+                INITIATOR => "icons/initiator.png",
+                PORT => "icons/port.png",
+                EXPANDER => "icons/expander.png",
+                TARGET => "icons/target.png",
                 &_ => return Err(Box::new(SimpleError("unexpected vertex name".to_string()))),
             };
             let img = Image::new()
-                .set("href", imguri)
+                .set("href", assets.href(imguri)?)
                 .set("x", x)
                 .set("y", y)
                 .set("width", vtx_width)
                 .set("height", vtx_height);
 
             vtx.geometry.x = x;
-            vtx.geometry.y = y.try_into().unwrap();
+            vtx.geometry.y = y;
             vtx.geometry.width = vtx_width;
             vtx.geometry.height = vtx_height;
+            bbox.include(x.into(), y.into(), vtx_width.into(), vtx_height.into());
 
+            let vtx_id = &vertex_ids[&vtx_fmri];
             let mut vtx_group = Group::new()
+                .set("id", vtx_id.clone())
+                .set("class", "vertex")
                 .set("onclick", "showInfo(evt)")
-                .set("name", vtx.name.clone())
-                .set("fmri", vtx_fmri)
+                .set("data-type", escape(&vtx.name))
+                .set("data-fmri", escape(&vtx_fmri))
+                .set("data-parents", adjacency[&vtx_fmri].0.clone())
+                .set("data-children", adjacency[&vtx_fmri].1.clone())
+                .set("tabindex", 0)
+                .set("role", "button")
                 .add(img);
 
-            for prop in &vtx.properties {
-                vtx_group = vtx_group.set(prop.name.clone(), prop.value.clone());
+            //
+            // Vertices are ordered within their rank, so keyboard navigation
+            // can move to the previous and next vertex in the rank.
+            //
+            if index > 0 {
+                vtx_group = vtx_group.set("data-prev", vertex_ids[&vertices[index - 1]].clone());
+            }
+            if index + 1 < vertices.len() {
+                vtx_group = vtx_group.set("data-next", vertex_ids[&vertices[index + 1]].clone());
+            }
+
+            if let Some(subtree) = subtrees.get(&vtx_fmri) {
+                let is_collapsed = collapsed.contains(vtx_fmri.as_str());
+                if is_collapsed {
+                    vtx_group = vtx_group.set("class", "vertex collapsed");
+                }
+                vtx_group = vtx_group
+                    .set("data-subtree", subtree.members.join(" "))
+                    .add(subtree_toggle(vtx_id, x + vtx_width, y, is_collapsed));
             }
 
-            document = document.add(vtx_group);
+            let label_text = match config.labels.get(&vtx.name) {
+                Some(prop) => vtx.label(prop),
+                None => None,
+            };
+            let description = match &label_text {
+                Some(text) => format!("{} {}", vtx.name, text),
+                None => format!("{} {}", vtx.name, vtx_fmri),
+            };
+            vtx_group = vtx_group.set("aria-label", escape(&description));
+            if let Some(text) = label_text {
+                let (label_x, label_y) = (x + (vtx_width / 2), y + vtx_height + 14);
+                let len = cmp::min(text.chars().count(), config.label_length);
+                bbox.include_text(label_x, label_y, len, 12, true);
+                vtx_group = vtx_group.add(label(&text, config.label_length, label_x, label_y));
+            }
+
+            vertex_groups.push(vtx_group);
         }
     }
 
     //
-    // Generate the SVG elements for all of the edges
+    // Draw a box around the targets in each enclosure.  The boxes go
+    // underneath the vertices, so they're added to the document first.
+    //
+    // If other vertices are placed between some of them, or some of them are
+    // in another rank, each run of them is boxed separately.
     //
+    for enclosure in digraph.enclosures() {
+        if enclosure.targets.len() < 2 {
+            continue;
+        }
+        let in_enclosure: HashSet<&str> = enclosure.targets.iter().map(|t| t.as_str()).collect();
+        for depth in 1..=max_depth {
+            let runs = column_hash[&depth].split(|fmri| !in_enclosure.contains(fmri.as_str()));
+            for run in runs.filter(|run| !run.is_empty()) {
+                let targets: Vec<&SasDigraphVertex> =
+                    run.iter().map(|fmri| &digraph.vertices[fmri]).collect();
+                let members: Vec<String> = run.iter().map(|fmri| vertex_ids[fmri].clone()).collect();
+                graph = graph.add(enclosure_box(
+                    &enclosure.label,
+                    enclosure.targets.len(),
+                    &targets,
+                    &members,
+                    &mut bbox,
+                ));
+            }
+        }
+    }
+    for vtx_group in vertex_groups {
+        graph = graph.add(vtx_group);
+    }
+
+    for (fmri, subtree) in &subtrees {
+        let vtx = &digraph.vertices[fmri];
+        let summary = subtree_summary(
+            layout,
+            vtx,
+            &vertex_ids[fmri],
+            subtree,
+            collapsed.contains(fmri.as_str()),
+            &mut bbox,
+        );
+        graph = graph.add(summary);
+    }
+
+    //
+    // Generate the SVG elements for all of the edges.  Edges are routed
+    // orthogonally: out of the source vertex along the rank axis, across
+    // the ranks along a channel in the gap between its rank and the next,
+    // and along the rank axis into the destination vertex.
+    //
+    let channel_space = layout.channel_space();
+    let channels = assign_channels(layout, digraph, &column_hash, max_depth);
+
     for depth in 1..=max_depth {
         let vertices = column_hash.get(&depth).unwrap();
         for v in vertices {
             let vtx_fmri: String = v.to_string();
             let vtx = digraph.vertices.get(&vtx_fmri).unwrap();
 
-            if vtx.outgoing_edges.is_none() {
-                continue;
-            }
+            let edges = match &vtx.outgoing_edges {
+                Some(edges) => edges,
+                None => continue,
+            };
+
+            //
+            // Edges leading from ports are drawn with a width proportional to
+            // the aggregate bandwidth of the link and annotated with its PHY
+            // width and rate.
+            //
+            let link = vtx.link_summary();
+
+            let (channel, num_channels) = channels[&vtx_fmri];
+            let start = layout.edge_start(vtx);
+            let channel_pos = start.0 + ((channel + 1) * channel_space) / (num_channels + 1);
 
-            let start_x1 = vtx.geometry.x + vtx_width;
-            let start_y1: u32 = vtx.geometry.y + (vtx_height / 2);
-            let start_x2 = start_x1 + 50;
-            let start_y2 = start_y1;
-            let line = Line::new()
-                .set("x1", start_x1)
-                .set("y1", start_y1)
-                .set("x2", start_x2)
-                .set("y2", start_y2)
-                .set("stroke", "black")
-                .set("stroke-width", "2");
-
-            document = document.add(line);
-
-            for edge_fmri in vtx.outgoing_edges.as_ref().unwrap() {
+            for edge_fmri in edges {
                 let edge_vtx = digraph.vertices.get(edge_fmri).unwrap();
-                let mid_x1 = start_x2;
-                let mid_y1 = start_y2;
-                let mid_x2 = start_x2;
-                let mid_y2 = edge_vtx.geometry.y + (vtx_height / 2);
-
-                let line = Line::new()
-                    .set("x1", mid_x1)
-                    .set("y1", mid_y1)
-                    .set("x2", mid_x2)
-                    .set("y2", mid_y2)
-                    .set("stroke", "black")
-                    .set("stroke-width", "2");
-
-                document = document.add(line);
-
-                let end_x1 = start_x2;
-                let end_y1 = edge_vtx.geometry.y + (vtx_height / 2);
-                let end_x2 = edge_vtx.geometry.x;
-                let end_y2 = end_y1;
-
-                let line = Line::new()
-                    .set("x1", end_x1)
-                    .set("y1", end_y1)
-                    .set("x2", end_x2)
-                    .set("y2", end_y2)
-                    .set("stroke", "black")
-                    .set("stroke-width", "2");
-
-                document = document.add(line);
+                let end = layout.edge_end(edge_vtx);
+
+                let path = edge_path(layout, start, channel_pos, end, link.as_ref(), &mut bbox)
+                    .set("data-src", vertex_ids[&vtx_fmri].clone())
+                    .set("data-dst", vertex_ids[edge_fmri].clone());
+                graph = graph.add(path);
+            }
+
+            if let Some(link) = &link {
+                let (start_x, start_y) = layout.point(start.0, start.1);
+                let (label_x, label_y) = match layout.orientation {
+                    Orientation::LeftToRight => (start_x + 4, start_y - 6),
+                    Orientation::TopToBottom => (start_x + 6, start_y + 12),
+                };
+                bbox.include_text(label_x, label_y, link.label.chars().count(), 10, false);
+                let mut annotation = label(&link.label, link.label.len(), label_x, label_y);
+                annotation.assign("class", "link-label");
+                annotation.assign("text-anchor", "start");
+                annotation.assign("font-size", 10);
+                annotation.assign("data-src", vertex_ids[&vtx_fmri].clone());
+                graph = graph.add(annotation);
             }
         }
     }
 
-    fs::create_dir_all(&config.outdir)?;
+    //
+    // Size the drawing to fit everything that was placed in it.  The viewBox
+    // maps the drawing coordinates onto the viewport, so the SVG scales to
+    // whatever size it is embedded at, and the width and height default the
+    // viewport to the natural size of the drawing.
+    //
+    let (view_x, view_y, svg_width, svg_height) = bbox.with_margin(layout.margin);
+    //
+    // The properties of the host and of each vertex are embedded as a JSON
+    // document, rather than as attributes of the elements that draw them, so
+    // that property names can't clash with SVG attributes and nothing is lost
+    // to the rules for attribute names.
+    //
+    let viewer_data = serde_json::to_string(&ViewerData::new(digraph))?;
+    let mut metadata = Element::new("metadata");
+    metadata.assign("id", "viewer-data");
+    metadata.append(svg::node::Text::new(escape(&viewer_data)));
+
+    document = document
+        .add(metadata)
+        .add(graph)
+        .set("viewBox", (view_x, view_y, svg_width, svg_height))
+        .set("width", svg_width)
+        .set("height", svg_height);
+
+    Ok(document)
+}
 
-    let src_dir_path = std::env::current_exe()?;
-    let src_dir = match src_dir_path.parent() {
-        Some (path) => path.to_str().unwrap(),
-        None => "/"
+//
+// Generates the HTML page, with the chassis view (if we have a manifest for
+// this chassis) embedded in the info panel and the specified frame, which
+// shows the SVG, filling the rest of the page.
+//
+fn build_html(
+    digraph: &SasDigraph,
+    manifest: Option<&ChassisManifest>,
+    assets: &Assets,
+    frame: &str,
+) -> Result<String, Box<dyn Error>> {
+    let html_code = include_str!("sastopo2svg.html");
+    let chassis_view = match manifest {
+        Some(manifest) => build_chassis_view(manifest, digraph, assets)?.to_string(),
+        None => String::new(),
     };
+    let mut html_code = html_code.replace(CHASSIS_VIEW_MARKER, &chassis_view);
+    html_code.push_str(frame);
+    html_code.push_str("</div></div></body></html>\n");
+    Ok(html_code)
+}
 
-    let asset_src_dir = format!("{}/assets", src_dir);
-    debug!("Copying image assets: {} to {}", asset_src_dir, config.outdir);
-    let mut options = fs_extra::dir::CopyOptions::new();
-    options.overwrite = true;
-    fs_extra::dir::copy(&asset_src_dir, &config.outdir, &options)?;
+//
+// The documents making up the webpage, rendered in memory.
+//
+struct Webpage {
+    svg: String,
+    html: String,
+    // directory of the chassis whose images the webpage references, if any
+    chassis: Option<String>,
+}
 
-    let svg_file = "sastopo.svg".to_string();
-    let svg_path = format!("{}/{}", config.outdir, svg_file);
-    debug!("Saving SVG to {}", svg_file);
-    svg::save(&svg_path, &document)?;
+//
+// Renders the SVG and the HTML page that embeds it, which reference the
+// images relative to the URL of the assets directory.
+//
+fn render_webpage(config: &Config, digraph: &mut SasDigraph) -> Result<Webpage, Box<dyn Error>> {
+    let assets = Assets::new(config, false)?;
+    let registry = ChassisRegistry::load(&assets.src_dir)?;
+    let manifest = registry.lookup(&digraph.product_id);
+    match manifest {
+        Some(manifest) => debug!("product {} is chassis {}", digraph.product_id, manifest.name),
+        None => debug!("no chassis manifest for product {}", digraph.product_id),
+    }
+
+    let document = build_svg(config, digraph, &assets)?;
 
     //
     // The SVG can be quite large depending on the size of the SAS fabric.
     // So to allow it to be more easily viewable in a browser, we embed the
-    // SVG in a scrollable HTML iframe.
+    // SVG in an HTML iframe that fills the rest of the page, where the script
+    // in the SVG lets it be panned and zoomed.
     //
-    let html_code = include_str!("sastopo2svg.html");
-    let html_path = format!("{}/sastopo2svg.html", config.outdir);
-    let svg_width = cmp::max(1200, max_depth * 250);
-    let svg_height = cmp::max(1100, max_height * 150);
-
-    let mut htmlfile = fs::File::create(&html_path)?;
-    htmlfile.write_fmt(format_args!("{}", html_code))?;
-    htmlfile.write_fmt(format_args!(
-        "<iframe src=\"{}\" width={} height={} scrollable=\"yes\" frameborder=\"no\" />",
-        svg_file, svg_width, svg_height
-    ))?;
-    htmlfile.write_fmt(format_args!("</div></div></body></html>\n"))?;
+    let frame = format!(
+        "<iframe id=\"sastopo-frame\" src=\"{}\" frameborder=\"no\"></iframe>",
+        SVG_FILE
+    );
+    Ok(Webpage {
+        svg: document.to_string(),
+        html: build_html(digraph, manifest, &assets, &frame)?,
+        chassis: manifest.map(|manifest| manifest.dir.clone()),
+    })
+}
+
+//
+// Generates the webpage, made up of the SVG, the HTML page that embeds it and
+// the images they reference, in the output directory.
+//
+fn build_webpage(config: &Config, digraph: &mut SasDigraph) -> Result<(), Box<dyn Error>> {
+    let webpage = render_webpage(config, digraph)?;
+
+    //
+    // Copy the vertex icons, and the images for this chassis if we have any,
+    // to the output directory, unless the webpage shares them with others.
+    //
+    fs::create_dir_all(&config.outdir)?;
+    if config.shared_assets.is_none() {
+        let assets = Assets::new(config, false)?;
+        let asset_dst_dir = format!("{}/assets", config.outdir);
+        fs::create_dir_all(&asset_dst_dir)?;
+
+        let mut asset_dirs = vec![format!("{}/icons", assets.src_dir)];
+        if let Some(chassis) = &webpage.chassis {
+            asset_dirs.push(format!("{}/{}", assets.src_dir, chassis));
+        }
+        let mut options = fs_extra::dir::CopyOptions::new();
+        options.overwrite = true;
+        for dir in asset_dirs {
+            debug!("Copying image assets: {} to {}", dir, asset_dst_dir);
+            fs_extra::dir::copy(&dir, &asset_dst_dir, &options)?;
+        }
+    }
+
+    let svg_path = format!("{}/{}", config.outdir, SVG_FILE);
+    debug!("Saving SVG to {}", svg_path);
+    fs::write(&svg_path, &webpage.svg)?;
+
+    let html_path = format!("{}/{}", config.outdir, HTML_FILE);
+    fs::write(&html_path, &webpage.html)?;
+    Ok(())
+}
+
+//
+// Generates a Graphviz DOT representation of the directed graph.  Vertices are
+// labelled the same way as in the SVG, and edges from ports with the state of
+// their links.
+//
+fn build_dot(config: &Config, digraph: &SasDigraph) -> String {
+    let quote = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+
+    let rankdir = match config.layout.orientation {
+        Orientation::LeftToRight => "LR",
+        Orientation::TopToBottom => "TB",
+    };
+    let mut dot = format!("digraph sastopo {{\n    rankdir={};\n    node [shape=box];\n", rankdir);
+
+    let mut fmris: Vec<&String> = digraph.vertices.keys().collect();
+    fmris.sort();
+    for fmri in &fmris {
+        let vtx = &digraph.vertices[*fmri];
+        let label = match config.labels.get(&vtx.name).and_then(|prop| vtx.label(prop)) {
+            Some(text) => format!("{}\\n{}", quote(&vtx.name), quote(&text)),
+            None => quote(&vtx.name),
+        };
+        dot.push_str(&format!("    \"{}\" [label=\"{}\"];\n", quote(fmri), label));
+    }
+    for fmri in &fmris {
+        let vtx = &digraph.vertices[*fmri];
+        let edges = match &vtx.outgoing_edges {
+            Some(edges) => edges,
+            None => continue,
+        };
+        let attrs = match vtx.link_summary() {
+            Some(link) if link.degraded => format!(" [label=\"{}\", style=dashed]", link.label),
+            Some(link) => format!(" [label=\"{}\"]", link.label),
+            None => String::new(),
+        };
+        for edge in edges {
+            dot.push_str(&format!("    \"{}\" -> \"{}\"{};\n", quote(fmri), quote(edge), attrs));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+//
+// Writes a single document, in the specified format, to out.  Any images are
+// embedded in the document, so that it stands alone.
+//
+fn write_document(
+    config: &Config,
+    digraph: &mut SasDigraph,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        OutputFormat::Svg => {
+            let assets = Assets::new(config, true)?;
+            let document = build_svg(config, digraph, &assets)?;
+            svg::write(&mut *out, &document)?;
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &ViewerData::new(digraph))?;
+            writeln!(out)?;
+        }
+        OutputFormat::Dot => out.write_all(build_dot(config, digraph).as_bytes())?,
+        OutputFormat::Html => {
+            let assets = Assets::new(config, true)?;
+            let registry = ChassisRegistry::load(&assets.src_dir)?;
+            let manifest = registry.lookup(&digraph.product_id);
+            let document = build_svg(config, digraph, &assets)?;
+
+            //
+            // The SVG is embedded in the page as a string, which the page
+            // turns into a blob URL for the iframe.  Unlike a data: URI, a
+            // blob URL has the same origin as the page, so the script in the
+            // SVG can still reach it.  Escaping every '<' keeps the SVG from
+            // ending the <script> element early.
+            //
+            let svg_string = serde_json::to_string(&document.to_string())?.replace('<', "\\u003c");
+            let frame = format!(
+                "<iframe id=\"sastopo-frame\" frameborder=\"no\"></iframe>\n\
+                 <script>\n\
+                 var svg = new Blob([{}], {{type: 'image/svg+xml'}});\n\
+                 document.getElementById('sastopo-frame').src = URL.createObjectURL(svg);\n\
+                 </script>\n",
+                svg_string
+            );
+            out.write_all(build_html(digraph, manifest, &assets, &frame)?.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+//
+// Opens the snapshot at the specified path, or stdin if the path is "-".
+// Snapshots compressed with gzip, zstd or xz are recognized by their magic
+// number and transparently decompressed.
+//
+fn open_xml(xml_path: &str) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    let mut input: Box<dyn Read> = if xml_path == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(fs::File::open(xml_path)?)
+    };
+
+    //
+    // Read enough to recognize the longest magic number and then put it back
+    // in front of the rest of the input.
+    //
+    let mut magic = Vec::with_capacity(XZ_MAGIC.len());
+    input.by_ref().take(XZ_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    let input = io::Cursor::new(magic.clone()).chain(input);
+
+    if magic.starts_with(GZIP_MAGIC) {
+        debug!("{} is gzip compressed", xml_path);
+        Ok(Box::new(flate2::read::MultiGzDecoder::new(input)))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        debug!("{} is zstd compressed", xml_path);
+        Ok(Box::new(zstd::stream::read::Decoder::new(input)?))
+    } else if magic.starts_with(XZ_MAGIC) {
+        debug!("{} is xz compressed", xml_path);
+        Ok(Box::new(xz2::read::XzDecoder::new_multi_decoder(input)))
+    } else {
+        Ok(Box::new(input))
+    }
+}
+
+//
+// Read in the serialized (XML) representation of a SAS topology and
+// recreate the SAS topology in the form of a SasDigraph structure.
+//
+fn load_digraph(config: &Config) -> Result<SasDigraph, Box<dyn Error>> {
+    //
+    // The decompressors buffer their own input, so this is the only buffer
+    // needed: it's between the parser and whatever open_xml returns.
+    //
+    let input = open_xml(&config.xml_path)?;
+    parse::parse_digraph(BufReader::new(input))
+}
+
+//
+// Entry points for benches/parse.rs, which compares the streaming parser with
+// deserializing the whole document at once.  Each returns the number of
+// vertices parsed.  Only built with the "bench" feature.
+//
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    use std::error::Error;
+    use std::io::Read;
+
+    pub fn parse_streaming<R: Read>(input: R) -> Result<usize, Box<dyn Error>> {
+        Ok(super::parse::parse_digraph(input)?.vertices.len())
+    }
+
+    pub fn parse_tree<R: Read>(input: R) -> Result<usize, Box<dyn Error>> {
+        Ok(super::parse::parse_digraph_tree(input)?.vertices.len())
+    }
+}
+
+//
+// Generate an SVG from the SasDigraph structure and save it, along with the
+// rest of the webpage, to the output directory, or write a single document
+// to stdout.
+//
+fn write_output(config: &Config, digraph: &mut SasDigraph) -> Result<(), Box<dyn Error>> {
+    match config.format {
+        Some(format) => {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            write_document(config, digraph, format, &mut out)?;
+            out.flush()?;
+        }
+        None => build_webpage(config, digraph)?,
+    }
+
     Ok(())
 }
 
 pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut digraph = load_digraph(config)?;
+    write_output(config, &mut digraph)
+}
+
+//
+// Check the SAS topology against the chassis profile at the specified path
+// and return the list of discrepancies found.  An empty list means the
+// topology matches the profile.
+//
+pub fn validate(config: &Config, profile_path: &str) -> Result<Vec<ValidationIssue>, Box<dyn Error>> {
+    let digraph = load_digraph(config)?;
+    let profile = profile::ChassisProfile::from_file(profile_path)?;
+
+    Ok(profile.validate(&digraph))
+}
+
+//
+// Validate the SAS topology, as validate() does, and then render it, as run()
+// does, reading the snapshot only once so that it can come from stdin.
+//
+pub fn validate_and_run(
+    config: &Config,
+    profile_path: &str,
+) -> Result<Vec<ValidationIssue>, Box<dyn Error>> {
+    let profile = profile::ChassisProfile::from_file(profile_path)?;
+    let mut digraph = load_digraph(config)?;
+    let issues = profile.validate(&digraph);
+    write_output(config, &mut digraph)?;
+
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::{canonical, DigraphBuilder};
+    use super::{
+        build_chassis_view, build_svg, load_digraph, write_document, Assets, ChassisManifest,
+        Config, OutputFormat, EXPANDER, INITIATOR, MAX_LINK_RATE, NEG_LINK_RATE, PORT, TARGET,
+    };
+
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+
+    use serde_json::{json, Value};
+    use xml::reader::{EventReader, XmlEvent};
+
+    fn test_config() -> Config {
+        let mut config = Config::new(String::new(), String::new());
+        config.asset_dir = Some(format!("{}/images", env!("CARGO_MANIFEST_DIR")));
+        config
+    }
+
     //
-    // Read in the serialized (XML) representation of a SAS topology and
-    // deserialize it into a TopoDigraphXML structure.
+    // Returns the value of every occurrence of the named attribute in the
+    // document.
     //
-    let xml_contents = fs::read_to_string(&config.xml_path)?;
-    let sasxml: TopoDigraphXML = serde_xml_rs::from_str(&xml_contents)?;
+    fn attr_values<'a>(document: &'a str, name: &str) -> Vec<&'a str> {
+        let pattern = format!(" {}=\"", name);
+        document
+            .match_indices(&pattern)
+            .map(|(pos, _)| {
+                let value = &document[pos + pattern.len()..];
+                &value[..value.find('"').unwrap()]
+            })
+            .collect()
+    }
 
-    let mut digraph = SasDigraph::new(
-        sasxml.product_id,
-        sasxml.nodename,
-        sasxml.os_version,
-        sasxml.timestamp,
-    );
+    #[test]
+    fn path_counts_multipath() {
+        let digraph = DigraphBuilder::new("Test")
+            .vertex(INITIATOR, "i0", &[], &["p0"])
+            .vertex(INITIATOR, "i1", &[], &["p1"])
+            .vertex(PORT, "p0", &[], &["e0"])
+            .vertex(PORT, "p1", &[], &["e0", "t1"])
+            .vertex(EXPANDER, "e0", &[], &["t0", "t1"])
+            .vertex(TARGET, "t0", &[], &[])
+            .vertex(TARGET, "t1", &[], &[])
+            .build();
+        let counts = digraph.path_counts();
+        assert_eq!(counts["e0"], 2);
+        assert_eq!(counts["t0"], 2);
+        assert_eq!(counts["t1"], 3);
+    }
+
+    #[test]
+    fn path_counts_cycle() {
+        //
+        // Build the digraph a few times, so that the vertices come out of the
+        // hash map in different orders.
+        //
+        for _ in 0..16 {
+            let digraph = DigraphBuilder::new("Test")
+                .vertex(INITIATOR, "i0", &[], &["e0"])
+                .vertex(EXPANDER, "e0", &[], &["e1"])
+                .vertex(EXPANDER, "e1", &[], &["e0", "t0"])
+                .vertex(TARGET, "t0", &[], &[])
+                .build();
+            let counts = digraph.path_counts();
+            assert_eq!(counts["e0"], 1);
+            assert_eq!(counts["e1"], 1);
+            assert_eq!(counts["t0"], 1);
+        }
+    }
 
     //
-    // Iterate through the TopoDigraphXML and recreate the SAS topology in the
-    // form of a SasDigraph structure.
+    // A degraded port affects the vertex it hangs off, and everything
+    // downstream of it however it's reached, but not its siblings.
     //
-    for vtxxml in sasxml.vertices.vertex {
-        // Convert hex string to a u64, skipping the leading '0x'
-        let instance = u64::from_str_radix(&vtxxml.instance[2..], 16)?;
+    #[test]
+    fn degraded_vertices() {
+        let healthy = [(MAX_LINK_RATE, "11,11"), (NEG_LINK_RATE, "11,11")];
+        let slow = [(MAX_LINK_RATE, "11,11"), (NEG_LINK_RATE, "11,10")];
+        let digraph = DigraphBuilder::new("Test")
+            .vertex(INITIATOR, "i0", &[], &["p0"])
+            .vertex(PORT, "p0", &healthy, &["e0"])
+            .vertex(EXPANDER, "e0", &[], &["p1", "p2"])
+            .vertex(PORT, "p1", &slow, &["e1"])
+            .vertex(PORT, "p2", &healthy, &["t2"])
+            .vertex(EXPANDER, "e1", &[], &["p3", "p4"])
+            .vertex(PORT, "p3", &healthy, &["t3"])
+            .vertex(PORT, "p4", &healthy, &["t2"])
+            .vertex(TARGET, "t2", &[], &[])
+            .vertex(TARGET, "t3", &[], &[])
+            .build();
+
+        let mut degraded: Vec<&str> = digraph.degraded_vertices().into_iter().collect();
+        degraded.sort_unstable();
+        assert_eq!(degraded, vec!["e0", "e1", "p1", "p3", "p4", "t2", "t3"]);
+    }
 
-        let mut vtx = match vtxxml.outgoing_edges {
-            Some(outgoing_edges) => {
-                let mut edges = Vec::new();
-                for edgexml in outgoing_edges.edges {
-                    edges.push(edgexml.fmri);
-                }
-                SasDigraphVertex::new(vtxxml.fmri, vtxxml.name, instance, Some(edges))
+    #[test]
+    fn multipath_target_at_different_depths() {
+        //
+        // The disk is placed in rank 3, where it's first reached from i0,
+        // and reached again from a port in rank 4, behind i1's expander.
+        //
+        let mut digraph = DigraphBuilder::new("Test")
+            .vertex(INITIATOR, "i0", &[], &["p0"])
+            .vertex(INITIATOR, "i1", &[], &["p1"])
+            .vertex(PORT, "p0", &[], &["t0"])
+            .vertex(PORT, "p1", &[], &["e1"])
+            .vertex(EXPANDER, "e1", &[], &["p2"])
+            .vertex(PORT, "p2", &[], &["t0"])
+            .vertex(TARGET, "t0", &[], &[])
+            .build();
+        let config = test_config();
+        let assets = Assets::new(&config, false).unwrap();
+        let document = build_svg(&config, &mut digraph, &assets).unwrap().to_string();
+
+        let view_box: Vec<i64> = attr_values(&document, "viewBox")[0]
+            .split_whitespace()
+            .map(|value| value.parse().unwrap())
+            .collect();
+        assert_eq!(document.matches("data-dst=").count(), 6);
+        for data in attr_values(&document, "d") {
+            let coords: Vec<i64> = data
+                .split_whitespace()
+                .filter_map(|token| token.parse().ok())
+                .collect();
+            for point in coords.chunks(2) {
+                assert!(
+                    point[0] >= view_box[0] && point[0] <= view_box[0] + view_box[2],
+                    "edge {:?} leaves the drawing",
+                    data
+                );
+                assert!(
+                    point[1] >= view_box[1] && point[1] <= view_box[1] + view_box[3],
+                    "edge {:?} leaves the drawing",
+                    data
+                );
             }
-            None => SasDigraphVertex::new(vtxxml.fmri, vtxxml.name, instance, None),
-        };
+        }
+    }
 
+    #[test]
+    fn subtrees_leave_out_multipathed_vertices() {
         //
-        // The XML contains a set of nested NvpairXML structures representing
-        // the node property groups and their contained properties.  We descend
-        // through these to build an array of SasDigraphProperty structs which
-        // will contains a subset of properties that we want to display when
-        // the vertex is clicked on.
+        // Disk 0 is attached through both expanders and Disk 1 only through
+        // e0, with e2 cascaded behind it.  Collapsing e0 only hides what
+        // can't be reached through e1.
         //
-        for pgnvl in vtxxml.propgroups {
-            let pgarr = pgnvl.nvlist_elements.unwrap();
-            for pg in pgarr {
-                let mut owned1;
-                let mut owned2;
-
-                let mut props: Option<&Vec<NvlistXmlArrayElement>> = None;
-                let mut pgname: &str = "";
-                if pg.nvpairs.is_some() {
-                    for pgnvp in pg.nvpairs.unwrap() {
-                        match pgnvp.name.unwrap().as_ref() {
-                            PG_NAME => {
-                                owned1 = pgnvp.value.unwrap();
-                                pgname = owned1.as_ref();
-                            }
-                            PG_VALS => {
-                                if pgnvp.nvlist_elements.is_some() {
-                                    owned2 = pgnvp.nvlist_elements.unwrap();
-                                    props = Some(owned2.as_ref());
-                                }
-                            }
-                            _ => {
-                                return Err(Box::new(SimpleError("Unexpected nvpair name".to_string())))
-                            }
-                        }
-                    }
-                }
+        let digraph = DigraphBuilder::new("Test")
+            .vertex(INITIATOR, "i0", &[], &["p0"])
+            .vertex(INITIATOR, "i1", &[], &["p1"])
+            .vertex(PORT, "p0", &[], &["e0"])
+            .vertex(PORT, "p1", &[], &["e1"])
+            .vertex(EXPANDER, "e0", &[], &["t0", "t1", "e2"])
+            .vertex(EXPANDER, "e1", &[], &["t0"])
+            .vertex(EXPANDER, "e2", &[], &["t2"])
+            .vertex(TARGET, "t0", &[], &[])
+            .vertex(TARGET, "t1", &[], &[])
+            .vertex(TARGET, "t2", &[], &[])
+            .build();
+        let vertex_ids = digraph
+            .vertices
+            .keys()
+            .map(|fmri| (fmri.clone(), fmri.clone()))
+            .collect();
+        let subtrees = digraph.expander_subtrees(&vertex_ids);
+
+        assert_eq!(subtrees.keys().collect::<Vec<_>>(), vec!["e0", "e2"]);
+        assert_eq!(subtrees["e0"].members, vec!["e2", "t1", "t2"]);
+        assert_eq!(subtrees["e0"].targets, 2);
+        assert_eq!(subtrees["e2"].members, vec!["t2"]);
+    }
 
-                // Sanity check against malformed XML
-                if pgname == "" {
-                    return Err(Box::new(SimpleError(format!(
-                        "malformed propgroup, {} not set",
-                        PG_NAME
-                    ))));
-                } else if props.is_none() {
-                    /*return Err(Box::new(SimpleError(
-                    format!("malformed propgroup, {} not set", PG_VALS))));*/
-                    continue;
-                }
+    #[test]
+    fn enclosure_boxes_only_cover_members() {
+        //
+        // The two disks share an enclosure, but the cascaded expander is
+        // placed between them in their rank.
+        //
+        let mut digraph = DigraphBuilder::new("Test")
+            .vertex(INITIATOR, "i0", &[], &["p0"])
+            .vertex(PORT, "p0", &[], &["e0"])
+            .vertex(EXPANDER, "e0", &[], &["t0", "e1", "t1"])
+            .vertex(EXPANDER, "e1", &[], &[])
+            .vertex(TARGET, "t0", &[("location", "Front Disk 0")], &[])
+            .vertex(TARGET, "t1", &[("location", "Front Disk 1")], &[])
+            .build();
+        let config = test_config();
+        let assets = Assets::new(&config, false).unwrap();
+        let document = build_svg(&config, &mut digraph, &assets).unwrap().to_string();
+        let view_box: Vec<i64> = attr_values(&document, "viewBox")[0]
+            .split_whitespace()
+            .map(|value| value.parse().unwrap())
+            .collect();
+
+        let expander = &digraph.vertices["e1"].geometry;
+        let (expander_x, expander_y) = (
+            (expander.x + (expander.width / 2)) as i64,
+            (expander.y + (expander.height / 2)) as i64,
+        );
+        let boxes: Vec<&str> = document
+            .split("<rect")
+            .skip(1)
+            .map(|rect| &rect[..rect.find('>').unwrap()])
+            .filter(|rect| rect.contains("#f4f4fa"))
+            .collect();
+        assert_eq!(boxes.len(), 2);
+        for rect in boxes {
+            let value = |name| attr_values(rect, name)[0].parse::<i64>().unwrap();
+            let (x, y, width, height) = (value("x"), value("y"), value("width"), value("height"));
+            assert!(
+                expander_x < x || expander_x > x + width || expander_y < y || expander_y > y + height,
+                "enclosure box covers the expander"
+            );
+            assert!(x >= view_box[0] && x + width <= view_box[0] + view_box[2]);
+            assert!(y >= view_box[1] && y + height <= view_box[1] + view_box[3]);
+        }
+    }
 
-                //
-                // The only things in the protocol property group is an nvlist
-                // representation of the FMRI, which we don't need as we
-                // already have the FMRI as a string in a separate field.
-                //
-                if pgname == "protocol" {
-                    continue;
-                }
+    //
+    // Compresses the hostile snapshot with each of the supported compressors
+    // (gzip twice over, as two concatenated members) and checks that every
+    // one of them loads into the same digraph as the uncompressed file.
+    //
+    #[test]
+    fn compressed_snapshots() {
+        let xml_path = format!("{}/tests/data/hostile.xml", env!("CARGO_MANIFEST_DIR"));
+        let xml = fs::read(&xml_path).unwrap();
+        let expected = canonical(&load_digraph(&Config::new(String::new(), xml_path)).unwrap());
+
+        let gzip = |data: &[u8]| {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let (first, second) = xml.split_at(xml.len() / 2);
+        let mut multi_gzip = gzip(first);
+        multi_gzip.extend(gzip(second));
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&xml).unwrap();
+
+        let compressed = vec![
+            ("gz", gzip(&xml)),
+            ("multi.gz", multi_gzip),
+            ("zst", zstd::encode_all(&xml[..], 0).unwrap()),
+            ("xz", xz.finish().unwrap()),
+        ];
+
+        let dir = env::temp_dir().join(format!("sastopo2svg-compressed-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (extension, data) in compressed {
+            assert_ne!(data, xml);
+            let path = dir.join(format!("hostile.{}", extension));
+            fs::write(&path, data).unwrap();
+            let config = Config::new(String::new(), path.to_str().unwrap().to_string());
+            assert_eq!(canonical(&load_digraph(&config).unwrap()), expected, "{}", extension);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-                for propnvl in props.unwrap() {
-                    let prop = parse_prop(&propnvl)?;
-                    vtx.properties.push(prop);
+    //
+    // Parses an SVG document, checking that the only event handlers in it are
+    // the viewer's own.  Returns the number of <script> elements and every
+    // attribute value and piece of text, with the strings in the viewer data
+    // in place of its JSON.
+    //
+    fn document_strings(document: &str) -> (usize, Vec<String>) {
+        fn json_strings(value: &Value, strings: &mut Vec<String>) {
+            match value {
+                Value::String(s) => strings.push(s.clone()),
+                Value::Array(values) => values.iter().for_each(|v| json_strings(v, strings)),
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        strings.push(key.clone());
+                        json_strings(value, strings);
+                    }
                 }
+                _ => {}
             }
         }
 
-        if vtx.name == INITIATOR {
-            digraph.initiators.push(vtx.fmri.clone());
+        let mut scripts = 0;
+        let mut in_data = false;
+        let mut strings = Vec::new();
+        for event in EventReader::from_str(document) {
+            match event.expect("document is not well-formed XML") {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    if name.local_name == "script" {
+                        scripts += 1;
+                    }
+                    in_data = false;
+                    for attr in attributes {
+                        if attr.name.local_name.starts_with("on") {
+                            assert!(
+                                ["showInfo(evt)", "toggleSubtree(evt)"].contains(&&*attr.value),
+                                "unexpected event handler {}={:?}",
+                                attr.name.local_name,
+                                attr.value
+                            );
+                        }
+                        in_data |= attr.name.local_name == "id" && attr.value == "viewer-data";
+                        strings.push(attr.value);
+                    }
+                }
+                XmlEvent::Characters(text) if in_data => {
+                    let data = serde_json::from_str(&text).expect("viewer data is not valid JSON");
+                    json_strings(&data, &mut strings);
+                }
+                XmlEvent::Characters(text) => strings.push(text),
+                XmlEvent::EndElement { .. } => in_data = false,
+                _ => {}
+            }
         }
-        digraph.vertices.insert(vtx.fmri.clone(), vtx);
+        (scripts, strings)
     }
 
     //
-    // Generate an SVG from the SasDigraph structure and save it to the
-    // specified file.
+    // The self-contained HTML document embeds the SVG in a <script> element as
+    // a JavaScript string.  Checks that the string can't end that element and
+    // that every hostile value in the snapshot is still only text once the
+    // string is decoded.
     //
-    build_svg(config, &mut digraph)?;
+    #[test]
+    fn escaping_html_document() {
+        let xml_path = format!("{}/tests/data/hostile.xml", env!("CARGO_MANIFEST_DIR"));
+        let mut digraph = load_digraph(&Config::new(String::new(), xml_path)).unwrap();
+        let mut html = Vec::new();
+        write_document(&test_config(), &mut digraph, OutputFormat::Html, &mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+
+        let start = html.find("new Blob([").unwrap() + "new Blob([".len();
+        let end = start + html[start..].find("], {type: 'image/svg+xml'})").unwrap();
+        let literal = &html[start..end];
+        assert!(!literal.contains('<'), "embedded SVG can end its <script> element");
+        assert!(
+            !html[..start].contains("alert(") && !html[end..].contains("alert("),
+            "snapshot data outside the embedded SVG"
+        );
+
+        let svg: String = serde_json::from_str(literal).unwrap();
+        let (scripts, strings) = document_strings(&svg);
+        assert_eq!(scripts, 1, "embedded SVG has injected <script> elements");
+        for hostile in &[
+            "\"><script>alert(1)</script>",
+            "' onmouseover='alert(2)",
+            "]]><script>alert(4)</script><![CDATA[",
+            "</title><script>alert(6)</script>",
+            "javascript:alert(8)",
+        ] {
+            assert!(strings.iter().any(|s| s == hostile), "{:?} did not survive as text", hostile);
+        }
+    }
 
-    Ok(())
+    //
+    // Checks that hostile slot locations, and a hostile chassis directory,
+    // come out of the chassis view as attribute values and text.
+    //
+    #[test]
+    fn escaping_chassis_view() {
+        let occupied = "\"><script>alert(1)</script>";
+        let empty = "' onmouseover='alert(2)";
+        let mut manifest: ChassisManifest = serde_json::from_value(json!({
+            "name": "Test",
+            "product-ids": ["Test"],
+            "views": [{ "name": "front", "image": "front.png", "width": 100, "height": 50 }],
+            "slots": [
+                { "location": occupied, "view": "front", "x": 0, "y": 0, "width": 10, "height": 50 },
+                { "location": empty, "view": "front", "x": 10, "y": 0, "width": 10, "height": 50 },
+            ],
+        }))
+        .unwrap();
+        manifest.dir = "x\" onload=\"alert(3)".to_string();
+
+        let digraph = DigraphBuilder::new("Test")
+            .vertex(TARGET, "t0", &[("location", occupied)], &[])
+            .build();
+        let assets = Assets::new(&test_config(), false).unwrap();
+        let view = build_chassis_view(&manifest, &digraph, &assets).unwrap().to_string();
+
+        let (scripts, strings) = document_strings(&view);
+        assert_eq!(scripts, 0, "chassis view has injected <script> elements");
+        for expected in &[
+            occupied.to_string(),
+            format!("{} (ok)", occupied),
+            empty.to_string(),
+            format!("{} (empty)", empty),
+        ] {
+            assert!(strings.iter().any(|s| s == expected), "{:?} did not survive as text", expected);
+        }
+        assert!(
+            strings.iter().any(|s| s.ends_with(&format!("{}/front.png", manifest.dir))),
+            "chassis directory did not survive as text"
+        );
+    }
 }
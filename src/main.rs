@@ -11,31 +11,132 @@ extern crate env_logger;
 extern crate log;
 
 extern crate getopts;
+extern crate num_cpus;
 use getopts::Options;
 
 use std::env;
 use std::process;
 use std::panic::panic_any;
+use std::str::FromStr;
 
 extern crate sastopo2svg;
 
+//
+// Address the webpage is served on by default in serve mode
+//
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
 fn usage(progname: &str, opts: &Options) {
-    let msg = format!("USAGE: {} -x XML -d <OUTPUT_DIR>", progname);
+    let msg = format!(
+        "USAGE: {} -x XML -d <OUTPUT_DIR>\n       {} -x XML -f svg|json|dot|html\n       \
+         {} -x XML -p PROFILE [-d <OUTPUT_DIR> | -f FORMAT]\n       \
+         {} -b DIR|GLOB [-j JOBS] -d <OUTPUT_DIR>\n       \
+         {} serve -x XML [-a ADDRESS] [-w]\n\n\
+         XML may be - to read from standard input.",
+        progname, progname, progname, progname, progname
+    );
     print!("{}", opts.usage(&msg));
 }
 
+//
+// Parse the value of the specified option, if present, exiting with a usage
+// message if it isn't valid.
+//
+fn parse_opt<T: FromStr>(
+    matches: &getopts::Matches,
+    opt: &str,
+    progname: &str,
+    opts: &Options,
+) -> Option<T> {
+    let value = matches.opt_str(opt)?;
+    match value.parse::<T>() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("invalid -{} argument: {}", opt, value);
+            usage(progname, opts);
+            process::exit(2);
+        }
+    }
+}
+
 fn main() {
     env_logger::init();
 
     let args: Vec<String> = env::args().collect();
     let progname = args[0].clone();
 
+    //
+    // The serve subcommand takes the same options as rendering to a
+    // directory, other than the ones that say where to put the output.
+    //
+    let serve_mode = args.len() > 1 && args[1] == "serve";
+    let opt_args = if serve_mode { &args[2..] } else { &args[1..] };
+
     let mut opts = Options::new();
     opts.optflag("h", "help", "print this usage message");
     opts.optopt("d", "OUTPUT_DIR", "Directory to output webpage to", "OUTPUT_DIR");
-    opts.optopt("x", "XML", "Output of sastopo -x", "XML");
+    opts.optopt("x", "XML", "Output of sastopo -x (- for standard input)", "XML");
+    opts.optopt(
+        "f",
+        "FORMAT",
+        "Write a single svg, json, dot or html document to standard output",
+        "FORMAT",
+    );
+    opts.optopt(
+        "b",
+        "BATCH",
+        "Render every snapshot in a directory, or matching a glob, into its own \
+         subdirectory of OUTPUT_DIR",
+        "DIR|GLOB",
+    );
+    opts.optopt(
+        "j",
+        "JOBS",
+        "Number of snapshots to render at once in batch mode (default: number of CPUs)",
+        "JOBS",
+    );
+    opts.optopt(
+        "a",
+        "ADDRESS",
+        &format!("Address to serve the webpage on in serve mode (default: {})", DEFAULT_ADDRESS),
+        "ADDRESS",
+    );
+    opts.optflag(
+        "w",
+        "watch",
+        "Re-render the webpage, and reload open pages, whenever XML changes in serve mode",
+    );
+    opts.optopt(
+        "p",
+        "PROFILE",
+        "Validate topology against chassis profile",
+        "PROFILE",
+    );
+    opts.optmulti(
+        "l",
+        "LABEL",
+        "Label vertices of type TYPE with property PROP (empty PROP for no label)",
+        "TYPE=PROP",
+    );
+    opts.optopt("L", "LABEL_LENGTH", "Truncate vertex labels to N characters", "N");
+    opts.optopt(
+        "o",
+        "ORIENTATION",
+        "Lay out the graph left-to-right (lr) or top-to-bottom (tb)",
+        "lr|tb",
+    );
+    opts.optopt("n", "NODE_SIZE", "Width and height of vertex icons", "PIXELS");
+    opts.optopt("r", "RANK_SPACING", "Gap between ranks of vertices", "PIXELS");
+    opts.optopt("s", "SIBLING_SPACING", "Gap between vertices in a rank", "PIXELS");
+    opts.optopt("m", "MARGIN", "Margin around the graph", "PIXELS");
+    opts.optopt(
+        "c",
+        "COLLAPSE",
+        "Collapse expanders with more than N disks downstream",
+        "N",
+    );
 
-    let matches = match opts.parse(&args[1..]) {
+    let matches = match opts.parse(opt_args) {
         Ok(m) => m,
         Err(e) => panic_any(e.to_string()),
     };
@@ -45,17 +146,58 @@ fn main() {
         process::exit(2);
     }
 
+    let profile_path = matches.opt_str("p");
+    let format: Option<sastopo2svg::OutputFormat> = parse_opt(&matches, "f", &progname, &opts);
+
+    if serve_mode {
+        if ["d", "f", "b", "p"].iter().any(|opt| matches.opt_present(opt)) {
+            eprintln!("serve can't be used with -d, -f, -b or -p");
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+    } else if matches.opt_present("a") || matches.opt_present("w") {
+        eprintln!("-a and -w can only be used with serve");
+        usage(&progname, &opts);
+        process::exit(2);
+    }
+
+    //
+    // The output directory is optional when writing a single document to
+    // stdout or serving the webpage, or when validating against a chassis
+    // profile, in which case we only generate the webpage if asked to.
+    //
     let outdir = match matches.opt_str("d") {
-        Some(path) => path,
+        Some(_) if format.is_some() => {
+            eprintln!("-d and -f can't be used together");
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+        Some(path) => Some(path),
+        None if serve_mode || profile_path.is_some() || format.is_some() => None,
         None => {
             eprintln!("-d argument is required");
             usage(&progname, &opts);
             process::exit(2);
         }
     };
+    let output = outdir.is_some() || format.is_some();
+
+    let batch_input = matches.opt_str("b");
+    if batch_input.is_some()
+        && (matches.opt_present("x") || format.is_some() || profile_path.is_some())
+    {
+        eprintln!("-b can't be used with -x, -f or -p");
+        usage(&progname, &opts);
+        process::exit(2);
+    } else if batch_input.is_none() && matches.opt_present("j") {
+        eprintln!("-j can only be used with -b");
+        usage(&progname, &opts);
+        process::exit(2);
+    }
 
     let xml_path = match matches.opt_str("x") {
         Some(path) => path,
+        None if batch_input.is_some() => String::new(),
         None => {
             eprintln!("-x argument is required");
             usage(&progname, &opts);
@@ -63,14 +205,150 @@ fn main() {
         }
     };
 
-    let config = sastopo2svg::Config::new(outdir, xml_path);
+    let mut config = sastopo2svg::Config::new(outdir.unwrap_or_default(), xml_path);
+    config.format = format;
+
+    let vertex_names = [
+        sastopo2svg::INITIATOR,
+        sastopo2svg::PORT,
+        sastopo2svg::EXPANDER,
+        sastopo2svg::TARGET,
+    ];
+    for label in matches.opt_strs("l") {
+        let (name, prop) = match label.split_once('=') {
+            Some((name, prop)) if vertex_names.contains(&name) => (name, prop),
+            _ => {
+                eprintln!("invalid -l argument: {}", label);
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+        };
+        if prop.is_empty() {
+            config.labels.remove(name);
+        } else {
+            config.labels.insert(name.to_string(), prop.to_string());
+        }
+    }
+
+    if let Some(len) = parse_opt::<usize>(&matches, "L", &progname, &opts) {
+        if len == 0 {
+            eprintln!("invalid -L argument: {}", len);
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+        config.label_length = len;
+    }
+
+    if let Some(orientation) = parse_opt(&matches, "o", &progname, &opts) {
+        config.layout.orientation = orientation;
+    }
+    if let Some(size) = parse_opt::<u32>(&matches, "n", &progname, &opts) {
+        if size == 0 {
+            eprintln!("invalid -n argument: {}", size);
+            usage(&progname, &opts);
+            process::exit(2);
+        }
+        config.layout.node_size = size;
+    }
+    if let Some(spacing) = parse_opt(&matches, "r", &progname, &opts) {
+        config.layout.rank_spacing = spacing;
+    }
+    if let Some(spacing) = parse_opt(&matches, "s", &progname, &opts) {
+        config.layout.sibling_spacing = spacing;
+    }
+    if let Some(margin) = parse_opt(&matches, "m", &progname, &opts) {
+        config.layout.margin = margin;
+    }
+    config.collapse_threshold = parse_opt(&matches, "c", &progname, &opts);
+
+    //
+    // In serve mode, keep serving the webpage until killed.
+    //
+    if serve_mode {
+        let address = matches.opt_str("a").unwrap_or_else(|| DEFAULT_ADDRESS.to_string());
+        if let Err(e) = sastopo2svg::serve(&config, &address, matches.opt_present("w")) {
+            eprintln!("An error occurred: {}", e);
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+
+    //
+    // In batch mode, render every snapshot and report the ones that couldn't
+    // be rendered, exiting with status 1 if there were any.
+    //
+    if let Some(input) = batch_input {
+        let jobs = match parse_opt::<usize>(&matches, "j", &progname, &opts) {
+            Some(0) => {
+                eprintln!("invalid -j argument: 0");
+                usage(&progname, &opts);
+                process::exit(2);
+            }
+            Some(jobs) => jobs,
+            None => num_cpus::get(),
+        };
+        let results = sastopo2svg::batch_inputs(&input)
+            .and_then(|xml_paths| sastopo2svg::run_batch(&config, &xml_paths, jobs));
+        match results {
+            Ok(results) => {
+                let failed: Vec<_> = results.iter().filter(|r| r.error.is_some()).collect();
+                for result in &failed {
+                    eprintln!("{}: {}", result.xml_path, result.error.as_ref().unwrap());
+                }
+                if !failed.is_empty() {
+                    eprintln!("{} of {} snapshot(s) failed to render", failed.len(), results.len());
+                    process::exit(1);
+                }
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("An error occurred: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    //
+    // In validation mode, report any discrepancies between the topology and
+    // the chassis profile, after rendering it if asked to, and exit with
+    // status 3 if there were any.  They're reported on stderr if a document
+    // is being written to stdout.
+    //
+    if let Some(profile_path) = profile_path {
+        let validated = if output {
+            sastopo2svg::validate_and_run(&config, &profile_path)
+        } else {
+            sastopo2svg::validate(&config, &profile_path)
+        };
+        let mut status = 0;
+        match validated {
+            Ok(issues) => {
+                for issue in &issues {
+                    if config.format.is_some() {
+                        eprintln!("{}", issue);
+                    } else {
+                        println!("{}", issue);
+                    }
+                }
+                if !issues.is_empty() {
+                    eprintln!("{} problem(s) found", issues.len());
+                    status = 3;
+                }
+            }
+            Err(e) => {
+                eprintln!("An error occurred: {}", e);
+                process::exit(1);
+            }
+        }
+        process::exit(status);
+    }
 
     match sastopo2svg::run(&config) {
         Ok(_r) => {
             process::exit(0);
         }
         Err(e) => {
-            eprintln!("An error occurred: {}", e.to_string());
+            eprintln!("An error occurred: {}", e);
             process::exit(1);
         }
     }
@@ -0,0 +1,553 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// Parsing of the serialized (XML) representation of a SAS topology, which
+// looks like:
+//
+// <topo-digraph product-id=".." nodename=".." os-version=".." timestamp="..">
+//   <vertices>
+//     <vertex name="target" instance="0x0" fmri="sas:///target=...">
+//       <nvpair name="property-groups" type="nvlist-array">
+//         <nvlist>
+//           <nvpair name="property-group-name" type="string" value="target"/>
+//           <nvpair name="property-values" type="nvlist-array">
+//             <nvlist>
+//               <nvpair name="property-name" type="string" value="model"/>
+//               <nvpair name="property-value" type="string" value="..."/>
+//             </nvlist>
+//             ...
+//           </nvpair>
+//         </nvlist>
+//         ...
+//       </nvpair>
+//       <outgoing-edges>
+//         <edge fmri="..."/>
+//       </outgoing-edges>
+//     </vertex>
+//     ...
+//   </vertices>
+// </topo-digraph>
+//
+// Array property values are nvpairs, with a type ending in "-array",
+// containing an nvpair per element.
+//
+use log::debug;
+
+use topo_digraph_xml::{PG_NAME, PG_VALS, PROP_NAME, PROP_VALUE};
+#[cfg(any(test, feature = "bench"))]
+use topo_digraph_xml::{NvlistXmlArrayElement, TopoDigraphXML};
+
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+use std::error::Error;
+use std::io::Read;
+
+use super::{
+    PropertyGroup, SasDigraph, SasDigraphProperty, SasDigraphVertex, SimpleError, INITIATOR,
+};
+
+//
+// Returns the value of the named attribute, if the element has it.
+//
+fn attr<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attr| attr.name.local_name == name)
+        .map(|attr| attr.value.as_str())
+}
+
+fn required_attr(
+    attributes: &[OwnedAttribute],
+    element: &str,
+    name: &str,
+) -> Result<String, Box<dyn Error>> {
+    match attr(attributes, name) {
+        Some(value) => Ok(value.to_string()),
+        None => Err(Box::new(SimpleError(format!(
+            "malformed {}, {} not set",
+            element, name
+        )))),
+    }
+}
+
+//
+// A property group, and the property in it, that the parser is in the middle
+// of.
+//
+#[derive(Debug, Default)]
+struct PartialPropGroup {
+    name: Option<String>,
+    properties: Vec<SasDigraphProperty>,
+}
+
+#[derive(Debug, Default)]
+struct PartialProp {
+    name: Option<String>,
+    value: Option<String>,
+    elements: Vec<String>,
+    is_array: bool,
+}
+
+//
+// Builds the SasDigraph incrementally as the XML is read, one vertex at a
+// time, so that only the vertex being parsed (rather than the whole document)
+// is held in memory on top of the digraph itself.
+//
+pub(crate) fn parse_digraph<R: Read>(input: R) -> Result<SasDigraph, Box<dyn Error>> {
+    let mut digraph: Option<SasDigraph> = None;
+    let mut vertex: Option<SasDigraphVertex> = None;
+    let mut propgroup: Option<PartialPropGroup> = None;
+    let mut prop: Option<PartialProp> = None;
+
+    //
+    // The depth of the current element in the document, and the depth of the
+    // vertex element, which is where we find everything else.
+    //
+    let mut depth = 0;
+    let mut vertex_depth = 0;
+
+    for event in EventReader::new(input) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                depth += 1;
+                let element = name.local_name.as_str();
+
+                if vertex.is_none() {
+                    match element {
+                        "topo-digraph" => {
+                            digraph = Some(SasDigraph::new(
+                                required_attr(&attributes, element, "product-id")?,
+                                required_attr(&attributes, element, "nodename")?,
+                                required_attr(&attributes, element, "os-version")?,
+                                required_attr(&attributes, element, "timestamp")?,
+                            ));
+                        }
+                        "vertex" => {
+                            let instance = required_attr(&attributes, element, "instance")?;
+                            // Convert hex string to a u64, skipping the leading '0x'
+                            let instance = u64::from_str_radix(
+                                instance.get(2..).unwrap_or(""),
+                                16,
+                            )?;
+                            vertex = Some(SasDigraphVertex::new(
+                                required_attr(&attributes, element, "fmri")?,
+                                required_attr(&attributes, element, "name")?,
+                                instance,
+                                None,
+                            ));
+                            vertex_depth = depth;
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                let vtx = vertex.as_mut().unwrap();
+
+                //
+                // Everything else is identified by its element name and its
+                // depth below the vertex.
+                //
+                match (depth - vertex_depth, element) {
+                    (1, "outgoing-edges") => {
+                        vtx.outgoing_edges.get_or_insert_with(Vec::new);
+                    }
+                    (2, "edge") => {
+                        let fmri = required_attr(&attributes, element, "fmri")?;
+                        vtx.outgoing_edges.get_or_insert_with(Vec::new).push(fmri);
+                    }
+                    (2, "nvlist") => propgroup = Some(PartialPropGroup::default()),
+                    (3, "nvpair") => {
+                        let pg = match propgroup.as_mut() {
+                            Some(pg) => pg,
+                            None => continue,
+                        };
+                        match attr(&attributes, "name") {
+                            Some(PG_NAME) => {
+                                pg.name = attr(&attributes, "value").map(|v| v.to_string());
+                            }
+                            Some(PG_VALS) => {}
+                            _ => {
+                                return Err(Box::new(SimpleError(
+                                    "Unexpected nvpair name".to_string(),
+                                )))
+                            }
+                        }
+                    }
+                    (4, "nvlist") => prop = Some(PartialProp::default()),
+                    (5, "nvpair") => {
+                        let p = match prop.as_mut() {
+                            Some(p) => p,
+                            None => continue,
+                        };
+                        match attr(&attributes, "name") {
+                            Some(PROP_NAME) => {
+                                p.name = attr(&attributes, "value").map(|v| v.to_string());
+                            }
+                            Some(PROP_VALUE) => {
+                                //
+                                // Array values are told apart by their type
+                                // (e.g. "string-array"), so an empty array
+                                // isn't mistaken for a scalar without a value.
+                                //
+                                let nvtype = attr(&attributes, "type").unwrap_or("");
+                                p.is_array = nvtype.ends_with("-array");
+                                p.value = attr(&attributes, "value").map(|v| v.to_string());
+                            }
+                            _ => {}
+                        }
+                    }
+                    (6, "nvpair") => {
+                        //
+                        // An element of an array value, which we join into a
+                        // string with the other elements, delimited by commas.
+                        //
+                        if let Some(p) = prop.as_mut() {
+                            if let (true, Some(value)) = (p.is_array, attr(&attributes, "value")) {
+                                p.elements.push(value.to_string());
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                let element = name.local_name.as_str();
+                if vertex.is_some() {
+                    match (depth - vertex_depth, element) {
+                        (0, "vertex") => {
+                            let vtx = vertex.take().unwrap();
+                            let digraph = match digraph.as_mut() {
+                                Some(digraph) => digraph,
+                                None => {
+                                    return Err(Box::new(SimpleError(
+                                        "vertex outside of topo-digraph".to_string(),
+                                    )))
+                                }
+                            };
+                            if vtx.name == INITIATOR {
+                                digraph.initiators.push(vtx.fmri.clone());
+                            }
+                            debug!("parsed vertex {}", vtx.fmri);
+                            digraph.vertices.insert(vtx.fmri.clone(), vtx);
+                        }
+                        (2, "nvlist") if propgroup.is_some() => {
+                            let pg = propgroup.take().unwrap();
+                            let pgname = match pg.name {
+                                Some(name) if !name.is_empty() => name,
+                                _ => {
+                                    return Err(Box::new(SimpleError(format!(
+                                        "malformed propgroup, {} not set",
+                                        PG_NAME
+                                    ))))
+                                }
+                            };
+
+                            //
+                            // The only things in the protocol property group
+                            // is an nvlist representation of the FMRI, which
+                            // we don't need as we already have the FMRI as a
+                            // string in a separate field.  Groups without
+                            // any values, which includes an empty
+                            // property-values nvpair, are skipped too.
+                            //
+                            if !pg.properties.is_empty() && pgname != "protocol" {
+                                let mut group = PropertyGroup::new(pgname);
+                                group.properties = pg.properties;
+                                vertex.as_mut().unwrap().propgroups.push(group);
+                            }
+                        }
+                        (4, "nvlist") if prop.is_some() && propgroup.is_some() => {
+                            let p = prop.take().unwrap();
+                            let value = if p.is_array {
+                                Some(p.elements.join(","))
+                            } else {
+                                p.value
+                            };
+                            match (p.name, value) {
+                                (Some(name), Some(value)) => propgroup
+                                    .as_mut()
+                                    .unwrap()
+                                    .properties
+                                    .push(SasDigraphProperty::new(name, value)),
+                                (name, _) => {
+                                    return Err(Box::new(SimpleError(format!(
+                                        "malformed property value nvlist: {:?}",
+                                        name
+                                    ))))
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    match digraph {
+        Some(digraph) => Ok(digraph),
+        None => Err(Box::new(SimpleError("no topo-digraph element found".to_string()))),
+    }
+}
+
+//
+// Parse an NvlistXmlArrayElement representing a topo property, extract the
+// prop name and value (as a string) and return a SasDigraphProperty.
+//
+#[cfg(any(test, feature = "bench"))]
+fn parse_prop(nvl: &NvlistXmlArrayElement) -> Result<SasDigraphProperty, Box<dyn Error>> {
+    let mut propname: Option<String> = None;
+    let mut propval: Option<String> = None;
+
+    if let Some(nvpairs) = nvl.nvpairs.as_ref() {
+        for nvpair in nvpairs {
+            match nvpair.name.as_ref().unwrap().as_ref() {
+                PROP_NAME => {
+                    propname = Some(nvpair.value.as_ref().unwrap().clone());
+                }
+                PROP_VALUE => {
+                    if let Some(elements) = nvpair.nvpair_elements.as_ref() {
+                        //
+                        // If nvpair_elements is something then this is an array
+                        // type in which case we iterate through the child nvpairs
+                        // and create a string with all the array values,
+                        // delimited by a comma.
+                        //
+                        let mut valarr = Vec::new();
+                        for elem in elements {
+                            valarr.push(elem.value.as_ref().unwrap().clone());
+                        }
+                        propval = Some(valarr.join(","));
+                    } else {
+                        propval = Some(nvpair.value.as_ref().unwrap().clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let (Some(name), Some(val)) = (propname, propval) {
+        Ok(SasDigraphProperty::new(name, val))
+    } else {
+        Err(Box::new(SimpleError(format!(
+            "malformed property value nvlist: {:?}",
+            nvl
+        ))))
+    }
+}
+
+//
+// Deserializes the whole XML document into a TopoDigraphXML structure and then
+// recreates the SAS topology from it.  This is how snapshots were parsed
+// before parse_digraph, and is kept so that the tests and benches/parse.rs can
+// compare the two.
+//
+#[cfg(any(test, feature = "bench"))]
+pub(crate) fn parse_digraph_tree<R: Read>(mut input: R) -> Result<SasDigraph, Box<dyn Error>> {
+    let mut xml_contents = String::new();
+    input.read_to_string(&mut xml_contents)?;
+    let sasxml: TopoDigraphXML = serde_xml_rs::from_str(&xml_contents)?;
+
+    let mut digraph = SasDigraph::new(
+        sasxml.product_id,
+        sasxml.nodename,
+        sasxml.os_version,
+        sasxml.timestamp,
+    );
+
+    //
+    // Iterate through the TopoDigraphXML and recreate the SAS topology in the
+    // form of a SasDigraph structure.
+    //
+    for vtxxml in sasxml.vertices.vertex {
+        // Convert hex string to a u64, skipping the leading '0x'
+        let instance = u64::from_str_radix(&vtxxml.instance[2..], 16)?;
+
+        let mut vtx = match vtxxml.outgoing_edges {
+            Some(outgoing_edges) => {
+                let mut edges = Vec::new();
+                for edgexml in outgoing_edges.edges {
+                    edges.push(edgexml.fmri);
+                }
+                SasDigraphVertex::new(vtxxml.fmri, vtxxml.name, instance, Some(edges))
+            }
+            None => SasDigraphVertex::new(vtxxml.fmri, vtxxml.name, instance, None),
+        };
+
+        //
+        // The XML contains a set of nested NvpairXML structures representing
+        // the node property groups and their contained properties.  We descend
+        // through these to build a PropertyGroup for each group, containing
+        // its SasDigraphProperty structs, which are displayed (grouped) when
+        // the vertex is clicked on.
+        //
+        for pgnvl in vtxxml.propgroups {
+            let pgarr = pgnvl.nvlist_elements.unwrap();
+            for pg in pgarr {
+                let mut owned1;
+                let mut owned2;
+
+                let mut props: Option<&Vec<NvlistXmlArrayElement>> = None;
+                let mut pgname: &str = "";
+                if let Some(pgnvps) = pg.nvpairs {
+                    for pgnvp in pgnvps {
+                        match pgnvp.name.unwrap().as_ref() {
+                            PG_NAME => {
+                                owned1 = pgnvp.value.unwrap();
+                                pgname = owned1.as_ref();
+                            }
+                            PG_VALS => {
+                                if let Some(elements) = pgnvp.nvlist_elements {
+                                    owned2 = elements;
+                                    props = Some(owned2.as_ref());
+                                }
+                            }
+                            _ => {
+                                return Err(Box::new(SimpleError("Unexpected nvpair name".to_string())))
+                            }
+                        }
+                    }
+                }
+
+                // Sanity check against malformed XML
+                if pgname.is_empty() {
+                    return Err(Box::new(SimpleError(format!(
+                        "malformed propgroup, {} not set",
+                        PG_NAME
+                    ))));
+                }
+
+                //
+                // Groups without any values are skipped.
+                //
+                let props = match props {
+                    Some(props) if !props.is_empty() => props,
+                    _ => continue,
+                };
+
+                //
+                // The only things in the protocol property group is an nvlist
+                // representation of the FMRI, which we don't need as we
+                // already have the FMRI as a string in a separate field.
+                //
+                if pgname == "protocol" {
+                    continue;
+                }
+
+                let mut propgroup = PropertyGroup::new(pgname.to_string());
+                for propnvl in props {
+                    let prop = parse_prop(propnvl)?;
+                    propgroup.properties.push(prop);
+                }
+                vtx.propgroups.push(propgroup);
+            }
+        }
+
+        if vtx.name == INITIATOR {
+            digraph.initiators.push(vtx.fmri.clone());
+        }
+        digraph.vertices.insert(vtx.fmri.clone(), vtx);
+    }
+
+    Ok(digraph)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::canonical;
+    use super::{parse_digraph, parse_digraph_tree};
+
+    use std::fs;
+
+    fn snapshot(name: &str) -> Vec<u8> {
+        fs::read(format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+    }
+
+    //
+    // Both parsers should recreate the same vertices, edges, property groups
+    // and values from a snapshot.
+    //
+    #[test]
+    fn streaming_matches_tree() {
+        for name in &["normal.xml", "hostile.xml"] {
+            let xml = snapshot(name);
+            let streamed = parse_digraph(&xml[..]).unwrap();
+            let tree = parse_digraph_tree(&xml[..]).unwrap();
+            assert!(!streamed.vertices.is_empty(), "{}", name);
+            assert_eq!(canonical(&streamed), canonical(&tree), "{}", name);
+        }
+    }
+
+    //
+    // The protocol group, and groups with an empty property-values nvpair,
+    // are left out, and array values are joined with commas.
+    //
+    #[test]
+    fn skipped_groups_and_arrays() {
+        let digraph = parse_digraph(&snapshot("normal.xml")[..]).unwrap();
+
+        let expander = &digraph.vertices["sas:///expander=5003048000000200"];
+        let names: Vec<&str> = expander.propgroups.iter().map(|pg| pg.name.as_str()).collect();
+        assert_eq!(names, vec!["expander"]);
+
+        let port = &digraph.vertices["sas://:start-phy=0:end-phy=3/port=5003048000000100"];
+        let rate = port.propgroups[0]
+            .properties
+            .iter()
+            .find(|prop| prop.name == "negotiated-link-rate")
+            .unwrap();
+        assert_eq!(rate.value, "11,11,10,11");
+
+        assert_eq!(digraph.initiators, vec!["sas:///initiator=5003048000000100"]);
+    }
+
+    //
+    // Whether a property value is an array is decided by its type, so an
+    // empty array is an empty value, while a scalar without a value is an
+    // error.
+    //
+    #[test]
+    fn array_values_by_type() {
+        let with_value = |value: &str| {
+            format!(
+                "<topo-digraph product-id=\"p\" nodename=\"n\" os-version=\"o\" timestamp=\"t\">\
+                 <vertices><vertex name=\"target\" instance=\"0x0\" fmri=\"sas:///target=0\">\
+                 <nvpair name=\"property-groups\" type=\"nvlist-array\"><nvlist>\
+                 <nvpair name=\"property-group-name\" type=\"string\" value=\"target\"/>\
+                 <nvpair name=\"property-values\" type=\"nvlist-array\"><nvlist>\
+                 <nvpair name=\"property-name\" type=\"string\" value=\"prop\"/>{}\
+                 </nvlist></nvpair></nvlist></nvpair></vertex></vertices></topo-digraph>",
+                value
+            )
+        };
+        let value = |xml: String| {
+            let digraph = parse_digraph(xml.as_bytes()).unwrap();
+            digraph.vertices["sas:///target=0"].propgroups[0].properties[0].value.clone()
+        };
+
+        let array = "<nvpair name=\"property-value\" type=\"uint32-array\">\
+                     <nvpair type=\"uint32\" value=\"1\"/><nvpair type=\"uint32\" value=\"2\"/>\
+                     </nvpair>";
+        assert_eq!(value(with_value(array)), "1,2");
+        let empty = "<nvpair name=\"property-value\" type=\"string-array\"/>";
+        assert_eq!(value(with_value(empty)), "");
+        let scalar = "<nvpair name=\"property-value\" type=\"string\" value=\"\"/>";
+        assert_eq!(value(with_value(scalar)), "");
+
+        let missing = "<nvpair name=\"property-value\" type=\"string\"/>";
+        let err = parse_digraph(with_value(missing).as_bytes()).unwrap_err();
+        assert!(err.to_string().starts_with("malformed property value nvlist"), "{}", err);
+    }
+}
@@ -0,0 +1,557 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// Chassis profiles describe what the SAS topology of a given server model is
+// expected to look like: which initiators and expanders should be present,
+// which slot locations should be populated, how many paths each slot should
+// have and the minimum acceptable negotiated link rate.  The profile is a
+// JSON document, for example:
+//
+// {
+//     "product-ids": [ "Joyent-M12G5" ],
+//     "min-link-rate": 12.0,
+//     "initiators": [
+//         { "location": "CPU2 SLOT 2 PCI-E 3.0 X16" }
+//     ],
+//     "expanders": [
+//         { "label": "front", "model": "SAS3x40" }
+//     ],
+//     "slots": [
+//         { "location": "Front Disk 0", "paths": 2, "expanders": [ "front" ] }
+//     ]
+// }
+//
+use serde::Deserialize;
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use super::{link_rate_gbps, SasDigraph, SasDigraphVertex};
+use super::{EXPANDER, INITIATOR, NEG_LINK_RATE, PORT, TARGET};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DeviceSpec {
+    // name used to refer to this device from a slot spec
+    label: Option<String>,
+    location: Option<String>,
+    manufacturer: Option<String>,
+    model: Option<String>,
+}
+
+impl DeviceSpec {
+    fn matches(&self, vtx: &SasDigraphVertex) -> bool {
+        let fields = [
+            ("location", &self.location),
+            ("manufacturer", &self.manufacturer),
+            ("model", &self.model),
+        ];
+        fields.iter().all(|(name, expected)| match expected {
+            Some(expected) => vtx.get_prop(name) == Some(expected.as_str()),
+            None => true,
+        })
+    }
+}
+
+impl fmt::Display for DeviceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut fields = Vec::new();
+        if let Some(label) = &self.label {
+            fields.push(format!("label={}", label));
+        }
+        if let Some(location) = &self.location {
+            fields.push(format!("location={}", location));
+        }
+        if let Some(manufacturer) = &self.manufacturer {
+            fields.push(format!("manufacturer={}", manufacturer));
+        }
+        if let Some(model) = &self.model {
+            fields.push(format!("model={}", model));
+        }
+        write!(f, "[{}]", fields.join(", "))
+    }
+}
+
+fn default_paths() -> u64 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SlotSpec {
+    location: String,
+    // minimum number of initiator to target paths
+    #[serde(default = "default_paths")]
+    paths: u64,
+    // labels of the expanders this slot must be connected through
+    #[serde(default)]
+    expanders: Vec<String>,
+    // minimum negotiated link rate (Gbit/s) for ports leading to this slot
+    min_link_rate: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ChassisProfile {
+    #[serde(default)]
+    product_ids: Vec<String>,
+    // minimum negotiated link rate (Gbit/s) for every port
+    min_link_rate: Option<f64>,
+    // device categories left out of the profile aren't checked
+    initiators: Option<Vec<DeviceSpec>>,
+    expanders: Option<Vec<DeviceSpec>>,
+    slots: Option<Vec<SlotSpec>>,
+}
+
+#[derive(Debug)]
+pub enum ValidationIssue {
+    ProductMismatch { expected: Vec<String>, found: String },
+    MissingDevice { kind: String, spec: String },
+    UnexpectedDevice { kind: String, fmri: String },
+    EmptySlot { location: String },
+    UnderPathedSlot { location: String, expected: u64, found: u64 },
+    WrongExpander { location: String, expander: String },
+    SlowLink { fmri: String, phy: u32, rate: Option<f64>, min: f64 },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::ProductMismatch { expected, found } => write!(
+                f,
+                "product ID {} is not one of: {}",
+                found,
+                expected.join(", ")
+            ),
+            ValidationIssue::MissingDevice { kind, spec } => {
+                write!(f, "missing {}: {}", kind, spec)
+            }
+            ValidationIssue::UnexpectedDevice { kind, fmri } => {
+                write!(f, "unexpected {}: {}", kind, fmri)
+            }
+            ValidationIssue::EmptySlot { location } => write!(f, "empty slot: {}", location),
+            ValidationIssue::UnderPathedSlot {
+                location,
+                expected,
+                found,
+            } => write!(
+                f,
+                "under-pathed slot: {} has {} path(s), expected {}",
+                location, found, expected
+            ),
+            ValidationIssue::WrongExpander { location, expander } => write!(
+                f,
+                "slot {} is not connected through expander {}",
+                location, expander
+            ),
+            ValidationIssue::SlowLink {
+                fmri,
+                phy,
+                rate: Some(rate),
+                min,
+            } => write!(
+                f,
+                "slow link: {} PHY {} negotiated {} Gbit/s, minimum is {} Gbit/s",
+                fmri, phy, rate, min
+            ),
+            ValidationIssue::SlowLink {
+                fmri,
+                phy,
+                rate: None,
+                ..
+            } => write!(f, "slow link: {} PHY {} is not up", fmri, phy),
+        }
+    }
+}
+
+impl ChassisProfile {
+    pub fn from_file(path: &str) -> Result<ChassisProfile, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let profile: ChassisProfile = serde_json::from_str(&contents)?;
+        Ok(profile)
+    }
+
+    //
+    // Match each device spec against the vertices of the specified type.
+    // Each vertex can only satisfy a single spec.  Unmatched specs and
+    // vertices are reported as issues.  Returns a hash map of spec labels to
+    // the FMRI of the vertex that matched them.
+    //
+    fn match_devices<'a>(
+        specs: &[DeviceSpec],
+        kind: &str,
+        vertices: &[&'a SasDigraphVertex],
+        issues: &mut Vec<ValidationIssue>,
+    ) -> HashMap<String, &'a str> {
+        let mut claimed: HashSet<&str> = HashSet::new();
+        let mut labels = HashMap::new();
+
+        for spec in specs {
+            let found = vertices
+                .iter()
+                .find(|vtx| !claimed.contains(vtx.fmri.as_str()) && spec.matches(vtx));
+            match found {
+                Some(vtx) => {
+                    claimed.insert(&vtx.fmri);
+                    if let Some(label) = &spec.label {
+                        labels.insert(label.clone(), vtx.fmri.as_str());
+                    }
+                }
+                None => issues.push(ValidationIssue::MissingDevice {
+                    kind: kind.to_string(),
+                    spec: spec.to_string(),
+                }),
+            }
+        }
+
+        for vtx in vertices {
+            if !claimed.contains(vtx.fmri.as_str()) {
+                issues.push(ValidationIssue::UnexpectedDevice {
+                    kind: kind.to_string(),
+                    fmri: vtx.fmri.clone(),
+                });
+            }
+        }
+        labels
+    }
+
+    //
+    // Check the digraph against this profile and return the list of
+    // discrepancies found.
+    //
+    pub fn validate(&self, digraph: &SasDigraph) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        if !self.product_ids.is_empty() && !self.product_ids.contains(&digraph.product_id) {
+            issues.push(ValidationIssue::ProductMismatch {
+                expected: self.product_ids.clone(),
+                found: digraph.product_id.clone(),
+            });
+        }
+
+        //
+        // Sort the vertices by FMRI so that the issues are reported in a
+        // stable order.
+        //
+        let mut vertices: Vec<&SasDigraphVertex> = digraph.vertices.values().collect();
+        vertices.sort_by(|a, b| a.fmri.cmp(&b.fmri));
+        let of_type = |name: &str| -> Vec<&SasDigraphVertex> {
+            vertices.iter().filter(|vtx| vtx.name == name).copied().collect()
+        };
+
+        if let Some(specs) = &self.initiators {
+            ChassisProfile::match_devices(specs, INITIATOR, &of_type(INITIATOR), &mut issues);
+        }
+        let expanders = match &self.expanders {
+            Some(specs) => {
+                ChassisProfile::match_devices(specs, EXPANDER, &of_type(EXPANDER), &mut issues)
+            }
+            None => HashMap::new(),
+        };
+
+        let incoming = digraph.incoming_edges();
+        let path_counts = digraph.path_counts();
+
+        let slots: &[SlotSpec] = match &self.slots {
+            Some(slots) => slots,
+            None => &[],
+        };
+        let mut slot_targets: HashMap<&str, Vec<&SasDigraphVertex>> = HashMap::new();
+        if self.slots.is_some() {
+            for vtx in of_type(TARGET) {
+                match vtx.get_prop("location") {
+                    Some(location) if slots.iter().any(|slot| slot.location == location) => {
+                        slot_targets.entry(location).or_default().push(vtx);
+                    }
+                    _ => issues.push(ValidationIssue::UnexpectedDevice {
+                        kind: TARGET.to_string(),
+                        fmri: vtx.fmri.clone(),
+                    }),
+                }
+            }
+        }
+
+        //
+        // The minimum link rate that applies to each port is the highest of
+        // the profile-wide minimum and those of any slots it leads to.
+        //
+        let mut port_min: HashMap<&str, f64> = HashMap::new();
+        if let Some(min) = self.min_link_rate {
+            for vtx in of_type(PORT) {
+                port_min.insert(&vtx.fmri, min);
+            }
+        }
+
+        for slot in slots {
+            let targets = match slot_targets.get(slot.location.as_str()) {
+                Some(targets) => targets,
+                None => {
+                    issues.push(ValidationIssue::EmptySlot {
+                        location: slot.location.clone(),
+                    });
+                    continue;
+                }
+            };
+
+            let found: u64 = targets
+                .iter()
+                .map(|vtx| path_counts.get(&vtx.fmri).copied().unwrap_or(0))
+                .sum();
+            if found < slot.paths {
+                issues.push(ValidationIssue::UnderPathedSlot {
+                    location: slot.location.clone(),
+                    expected: slot.paths,
+                    found,
+                });
+            }
+
+            let mut ancestors: HashSet<&str> = HashSet::new();
+            for vtx in targets {
+                ancestors.extend(digraph.ancestors(&incoming, &vtx.fmri));
+            }
+
+            for label in &slot.expanders {
+                //
+                // If the labelled expander wasn't found then that has already
+                // been reported as a missing device.
+                //
+                if let Some(fmri) = expanders.get(label) {
+                    if !ancestors.contains(fmri) {
+                        issues.push(ValidationIssue::WrongExpander {
+                            location: slot.location.clone(),
+                            expander: label.clone(),
+                        });
+                    }
+                }
+            }
+
+            if let Some(min) = slot.min_link_rate {
+                for fmri in ancestors {
+                    if digraph.vertices[fmri].name == PORT {
+                        let entry = port_min.entry(fmri).or_insert(min);
+                        if *entry < min {
+                            *entry = min;
+                        }
+                    }
+                }
+            }
+        }
+
+        for vtx in of_type(PORT) {
+            let min = match port_min.get(vtx.fmri.as_str()) {
+                Some(min) => *min,
+                None => continue,
+            };
+            let start_phy = vtx.phy_range().map_or(0, |(start, _)| start);
+            for (idx, code) in vtx.link_rates(NEG_LINK_RATE).into_iter().enumerate() {
+                match link_rate_gbps(code) {
+                    Some(rate) if rate >= min => {}
+                    rate => issues.push(ValidationIssue::SlowLink {
+                        fmri: vtx.fmri.clone(),
+                        phy: start_phy + idx as u32,
+                        rate,
+                        min,
+                    }),
+                }
+            }
+        }
+
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChassisProfile, ValidationIssue};
+    use crate::fixtures::DigraphBuilder;
+    use crate::{SasDigraph, EXPANDER, INITIATOR, PORT, TARGET};
+
+    use serde_json::{json, Value};
+
+    //
+    // Two initiators, each with a port leading to an expander.  Disk 0 is
+    // attached to both expanders and Disk 1 only to the front one.  The first
+    // port negotiated the specified link rate.
+    //
+    fn digraph(rate: &str) -> SasDigraph {
+        let port0 = "sas://:start-phy=0:end-phy=0/port=p0";
+        let port1 = "sas://:start-phy=4:end-phy=4/port=p1";
+        DigraphBuilder::new("Test")
+            .vertex(INITIATOR, "sas:///initiator=i0", &[("location", "Slot 1")], &[port0])
+            .vertex(INITIATOR, "sas:///initiator=i1", &[("location", "Slot 2")], &[port1])
+            .vertex(
+                PORT,
+                port0,
+                &[("negotiated-link-rate", rate), ("max-link-rate", "11")],
+                &["sas:///expander=e0"],
+            )
+            .vertex(
+                PORT,
+                port1,
+                &[("negotiated-link-rate", "11"), ("max-link-rate", "11")],
+                &["sas:///expander=e1"],
+            )
+            .vertex(
+                EXPANDER,
+                "sas:///expander=e0",
+                &[("model", "front")],
+                &["sas:///target=t0", "sas:///target=t1"],
+            )
+            .vertex(EXPANDER, "sas:///expander=e1", &[("model", "rear")], &["sas:///target=t0"])
+            .vertex(TARGET, "sas:///target=t0", &[("location", "Disk 0")], &[])
+            .vertex(TARGET, "sas:///target=t1", &[("location", "Disk 1")], &[])
+            .build()
+    }
+
+    //
+    // Returns a profile that the digraph above matches, with the specified
+    // fields replaced.
+    //
+    fn profile(changes: Value) -> ChassisProfile {
+        let mut profile = json!({
+            "product-ids": ["Test"],
+            "min-link-rate": 12.0,
+            "initiators": [{ "location": "Slot 1" }, { "location": "Slot 2" }],
+            "expanders": [
+                { "label": "front", "model": "front" },
+                { "label": "rear", "model": "rear" }
+            ],
+            "slots": [
+                { "location": "Disk 0", "paths": 2, "expanders": ["front", "rear"] },
+                { "location": "Disk 1", "expanders": ["front"] }
+            ]
+        });
+        for (field, value) in changes.as_object().unwrap() {
+            profile[field] = value.clone();
+        }
+        serde_json::from_value(profile).unwrap()
+    }
+
+    fn validate(changes: Value) -> Vec<ValidationIssue> {
+        profile(changes).validate(&digraph("11"))
+    }
+
+    #[test]
+    fn matching_topology_has_no_issues() {
+        assert!(validate(json!({})).is_empty());
+    }
+
+    #[test]
+    fn product_mismatch() {
+        let issues = validate(json!({ "product-ids": ["Other"] }));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0],
+            ValidationIssue::ProductMismatch { found, .. } if found == "Test"));
+    }
+
+    #[test]
+    fn missing_device() {
+        let issues = validate(json!({
+            "initiators": [
+                { "location": "Slot 1" }, { "location": "Slot 2" }, { "location": "Slot 3" }
+            ]
+        }));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0],
+            ValidationIssue::MissingDevice { kind, spec }
+                if kind == INITIATOR && spec == "[location=Slot 3]"));
+    }
+
+    #[test]
+    fn unexpected_devices() {
+        let issues = validate(json!({
+            "initiators": [{ "location": "Slot 1" }],
+            "slots": [{ "location": "Disk 0", "paths": 2 }]
+        }));
+        assert_eq!(issues.len(), 2);
+        assert!(matches!(&issues[0],
+            ValidationIssue::UnexpectedDevice { kind, fmri }
+                if kind == INITIATOR && fmri == "sas:///initiator=i1"));
+        assert!(matches!(&issues[1],
+            ValidationIssue::UnexpectedDevice { kind, fmri }
+                if kind == TARGET && fmri == "sas:///target=t1"));
+    }
+
+    #[test]
+    fn empty_slot() {
+        let issues = validate(json!({
+            "slots": [
+                { "location": "Disk 0", "paths": 2 },
+                { "location": "Disk 1" },
+                { "location": "Disk 2" }
+            ]
+        }));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0],
+            ValidationIssue::EmptySlot { location } if location == "Disk 2"));
+    }
+
+    #[test]
+    fn under_pathed_slot() {
+        let issues = validate(json!({
+            "slots": [{ "location": "Disk 0", "paths": 2 }, { "location": "Disk 1", "paths": 2 }]
+        }));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0],
+            ValidationIssue::UnderPathedSlot { location, expected: 2, found: 1 }
+                if location == "Disk 1"));
+    }
+
+    #[test]
+    fn wrong_expander() {
+        let issues = validate(json!({
+            "slots": [
+                { "location": "Disk 0", "paths": 2 },
+                { "location": "Disk 1", "expanders": ["front", "rear"] }
+            ]
+        }));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0],
+            ValidationIssue::WrongExpander { location, expander }
+                if location == "Disk 1" && expander == "rear"));
+    }
+
+    #[test]
+    fn slow_link() {
+        let issues = profile(json!({})).validate(&digraph("10"));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0],
+            ValidationIssue::SlowLink { fmri, phy: 0, rate: Some(rate), .. }
+                if fmri.ends_with("port=p0") && (*rate - 6.0).abs() < f64::EPSILON));
+    }
+
+    #[test]
+    fn link_down() {
+        let issues = profile(json!({})).validate(&digraph("0"));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0],
+            ValidationIssue::SlowLink { phy: 0, rate: None, .. }));
+    }
+
+    #[test]
+    fn slot_link_rate() {
+        //
+        // A slot's minimum applies to the ports leading to it, on top of the
+        // profile-wide minimum.
+        //
+        let issues = profile(json!({
+            "min-link-rate": 3.0,
+            "slots": [
+                { "location": "Disk 0", "paths": 2 },
+                { "location": "Disk 1", "min-link-rate": 12.0 }
+            ]
+        }))
+        .validate(&digraph("10"));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(&issues[0],
+            ValidationIssue::SlowLink { rate: Some(_), min, .. } if (*min - 12.0).abs() < f64::EPSILON));
+    }
+}
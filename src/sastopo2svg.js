@@ -1,52 +1,824 @@
 'use strict';
 
-var chassis_images = [
-    'Joyent-M12G5',
-    'Joyent-S10G5'
-];
+//
+// The host and vertex properties, which build_svg embeds in the SVG as JSON.
+// Vertices are keyed by FMRI.
+//
+var viewer_data;
+
+//
+// An SVG written on its own (by "sastopo2svg -f svg") isn't embedded in the
+// viewer page, so there's no info panel or toolbar to drive.
+//
+var standalone = false;
 
 //
 // Populate the Host Information table when the document is loaded.
 //
-var front_image_cell;
-var rear_image_cell;
 var product_id;
+var bays = [];
 document.addEventListener('DOMContentLoaded', function () {
     console.log('iframe domain is: ' + document.domain);
     var parentDoc = window.parent.document;
-    var hostprops = document.getElementById('hostprops');
-    product_id = hostprops.getAttribute('product-id');
+    viewer_data = JSON.parse(
+        document.getElementById('viewer-data').textContent);
+
+    if (window.parent === window) {
+        standalone = true;
+        refreshCollapsed();
+        initPanZoom();
+        selectFromFragment();
+        window.addEventListener('hashchange', selectFromFragment);
+        return;
+    }
+
+    var host = viewer_data.host;
+    product_id = host['product-id'];
 
     var cell = parentDoc.getElementById('product-id');
-    cell.innerHTML = product_id;
+    cell.textContent = product_id;
 
     cell = parentDoc.getElementById('nodename');
-    cell.innerHTML = hostprops.getAttribute('nodename');
+    cell.textContent = host.nodename;
 
     cell = parentDoc.getElementById('os-version');
-    cell.innerHTML = hostprops.getAttribute('os-version');
+    cell.textContent = host['os-version'];
 
     cell = parentDoc.getElementById('timestamp');
-    cell.innerHTML = hostprops.getAttribute('timestamp');
-
-    if (chassis_images.includes(product_id)) {
-        var hostinfo = parentDoc.getElementById('hostinfo');
-        var imgrow = hostinfo.insertRow(-1);
-        front_image_cell = imgrow.insertCell(-1);
-        front_image_cell.colSpan = 2;
-        let img_file = product_id + '-front.png';
-        front_image_cell.innerHTML = '<center><img src=\'assets/' +
-            product_id + '/' + img_file + '\'></img></center>';
-
-        imgrow = hostinfo.insertRow(-1);
-        rear_image_cell = imgrow.insertCell(-1);
-        rear_image_cell.colSpan = 2;
-        img_file = product_id + '-rear.png';
-        rear_image_cell.innerHTML = '<center><img src=\'assets/' +
-            product_id + '/' + img_file + '\'></img></center>';
+    cell.textContent = host.timestamp;
+
+    //
+    // The chassis view is only generated if there are images for this server
+    // product.  Clicking on one of its bays selects the vertex in that slot.
+    //
+    bays = parentDoc.querySelectorAll('#chassis-view .bay');
+    for (let i = 0; i < bays.length; i++) {
+        bays[i].addEventListener('click', function () {
+            selectLocation(bays[i].getAttribute('data-location'));
+        });
     }
+
+    refreshCollapsed();
+    initPanZoom();
+
+    selectFromFragment();
+    window.parent.addEventListener('hashchange', selectFromFragment);
+
+    initKeyboard();
+
+    parentDoc.getElementById('zoom-in').addEventListener('click', function () {
+        zoomAt(1 / ZOOM_STEP, viewCenter());
+    });
+    parentDoc.getElementById('zoom-out').addEventListener('click',
+        function () {
+        zoomAt(ZOOM_STEP, viewCenter());
+    });
+    parentDoc.getElementById('zoom-fit').addEventListener('click',
+        function () {
+        fitView();
+    });
+
+    //
+    // Searching matches against the properties of every vertex.  Enter (or the
+    // next button) cycles forwards through the matches and Shift+Enter (or
+    // the previous button) cycles backwards.
+    //
+    var search = parentDoc.getElementById('search');
+    search.addEventListener('input', function () {
+        runSearch(search.value);
+    });
+    search.addEventListener('keydown', function (evt) {
+        if (evt.key === 'Enter') {
+            evt.preventDefault();
+            cycleMatch(evt.shiftKey ? -1 : 1);
+        } else if (evt.key === 'Escape') {
+            var selected = document.querySelector('g.vertex.selected');
+            search.blur();
+            if (selected !== null) {
+                selected.focus({preventScroll: true});
+            }
+        }
+    });
+    parentDoc.getElementById('search-prev').addEventListener('click',
+        function () {
+        cycleMatch(-1);
+    });
+    parentDoc.getElementById('search-next').addEventListener('click',
+        function () {
+        cycleMatch(1);
+    });
+
+    //
+    // Redisplay the selected vertex when "show all properties" is toggled.
+    //
+    parentDoc.getElementById('show-all').addEventListener('change',
+        function () {
+        var selected = document.querySelector('g.vertex.selected');
+        if (selected !== null) {
+            selectVertex(selected);
+        }
+    });
 });
 
+//
+// Pan and zoom.  The graph is looked at through the viewBox of the root SVG
+// element, which is kept at the same aspect ratio as the iframe so that the
+// graph is scaled uniformly.  base is the viewBox emitted by build_svg, which
+// covers the whole graph.
+//
+var SVG_NS = 'http://www.w3.org/2000/svg';
+var XLINK_NS = 'http://www.w3.org/1999/xlink';
+var ZOOM_STEP = 1.25;
+var MINIMAP_SIZE = 200;
+var base;
+var view;
+var minimap;
+var minimap_view;
+var minimap_width;
+var minimap_height;
+var drag = null;
+var dragged = false;
+var minimap_drag = false;
+
+function initPanZoom() {
+    var root = document.documentElement;
+    var vb = root.viewBox.baseVal;
+    base = {x: vb.x, y: vb.y, width: vb.width, height: vb.height};
+
+    root.setAttribute('width', '100%');
+    root.setAttribute('height', '100%');
+    createMinimap();
+    fitView();
+
+    window.addEventListener('resize', function () {
+        setView(view);
+    });
+
+    root.addEventListener('wheel', function (evt) {
+        evt.preventDefault();
+        zoomAt(evt.deltaY < 0 ? 1 / ZOOM_STEP : ZOOM_STEP,
+            toGraph(evt.clientX, evt.clientY));
+    }, {passive: false});
+
+    //
+    // Dragging anywhere on the graph pans it.  A small movement threshold
+    // keeps ordinary clicks on vertices working.
+    //
+    root.addEventListener('mousedown', function (evt) {
+        if (evt.button === 0) {
+            drag = {x: evt.clientX, y: evt.clientY, view_x: view.x,
+                view_y: view.y};
+            dragged = false;
+        }
+    });
+    window.addEventListener('mousemove', function (evt) {
+        if (minimap_drag) {
+            centerOnMinimap(evt);
+            return;
+        }
+        if (drag === null) {
+            return;
+        }
+        var dx = evt.clientX - drag.x;
+        var dy = evt.clientY - drag.y;
+        if (!dragged && Math.abs(dx) + Math.abs(dy) < 4) {
+            return;
+        }
+        dragged = true;
+        var scale = view.width / window.innerWidth;
+        setView({x: drag.view_x - dx * scale, y: drag.view_y - dy * scale,
+            width: view.width, height: view.height});
+    });
+    window.addEventListener('mouseup', function () {
+        drag = null;
+        minimap_drag = false;
+    });
+
+    //
+    // The click at the end of a drag shouldn't select whatever is under the
+    // pointer.
+    //
+    root.addEventListener('click', function (evt) {
+        if (dragged) {
+            evt.stopPropagation();
+            evt.preventDefault();
+            dragged = false;
+        }
+    }, true);
+}
+
+//
+// Set the viewBox to the specified view, adjusting its height to the aspect
+// ratio of the iframe.
+//
+function setView(v) {
+    var aspect = window.innerWidth / window.innerHeight;
+    var height = v.width / aspect;
+    view = {x: v.x, y: v.y + (v.height - height) / 2, width: v.width,
+        height: height};
+    document.documentElement.setAttribute('viewBox',
+        [view.x, view.y, view.width, view.height].join(' '));
+    updateMinimap();
+}
+
+//
+// Zoom to fit the whole graph in the iframe.
+//
+function fitView() {
+    var aspect = window.innerWidth / window.innerHeight;
+    var width = Math.max(base.width, base.height * aspect);
+    var height = width / aspect;
+    setView({x: base.x - (width - base.width) / 2,
+        y: base.y - (height - base.height) / 2, width: width, height: height});
+}
+
+//
+// Zoom in (factor < 1) or out (factor > 1), keeping the point p in graph
+// coordinates where it is.
+//
+function zoomAt(factor, p) {
+    var max_width = 4 * Math.max(base.width,
+        base.height * window.innerWidth / window.innerHeight);
+    var width = Math.min(Math.max(view.width * factor, 50), max_width);
+    factor = width / view.width;
+    setView({x: p.x - (p.x - view.x) * factor,
+        y: p.y - (p.y - view.y) * factor, width: width,
+        height: view.height * factor});
+}
+
+function viewCenter() {
+    return {x: view.x + view.width / 2, y: view.y + view.height / 2};
+}
+
+//
+// Convert a point in iframe (client) coordinates to graph coordinates.
+//
+function toGraph(client_x, client_y) {
+    var scale = view.width / window.innerWidth;
+    return {x: view.x + client_x * scale, y: view.y + client_y * scale};
+}
+
+//
+// Pan so that the specified element is in the middle of the iframe.
+//
+function centerOn(elem) {
+    var bb = elem.getBBox();
+    setView({x: bb.x + bb.width / 2 - view.width / 2,
+        y: bb.y + bb.height / 2 - view.height / 2, width: view.width,
+        height: view.height});
+}
+
+//
+// The minimap is a scaled down copy of the whole graph, drawn in the bottom
+// right corner of the iframe, with the part that is currently in view
+// outlined.  Clicking or dragging on it pans the view.
+//
+function createMinimap() {
+    var ratio = base.width / base.height;
+    minimap_width = Math.max(20, ratio >= 1 ? MINIMAP_SIZE :
+        MINIMAP_SIZE * ratio);
+    minimap_height = Math.max(20, ratio >= 1 ? MINIMAP_SIZE / ratio :
+        MINIMAP_SIZE);
+
+    minimap = document.createElementNS(SVG_NS, 'svg');
+    minimap.setAttribute('id', 'minimap');
+    minimap.setAttribute('viewBox',
+        [base.x, base.y, base.width, base.height].join(' '));
+    minimap.setAttribute('preserveAspectRatio', 'none');
+
+    var background = document.createElementNS(SVG_NS, 'rect');
+    background.setAttribute('x', base.x);
+    background.setAttribute('y', base.y);
+    background.setAttribute('width', base.width);
+    background.setAttribute('height', base.height);
+    background.setAttribute('fill', 'white');
+    background.setAttribute('fill-opacity', '0.9');
+    background.setAttribute('stroke', '#808080');
+    background.setAttribute('vector-effect', 'non-scaling-stroke');
+    minimap.appendChild(background);
+
+    var copy = document.createElementNS(SVG_NS, 'use');
+    copy.setAttribute('href', '#graph');
+    copy.setAttributeNS(XLINK_NS, 'xlink:href', '#graph');
+    copy.setAttribute('pointer-events', 'none');
+    minimap.appendChild(copy);
+
+    minimap_view = document.createElementNS(SVG_NS, 'rect');
+    minimap_view.setAttribute('fill', '#FF9900');
+    minimap_view.setAttribute('fill-opacity', '0.2');
+    minimap_view.setAttribute('stroke', '#FF6600');
+    minimap_view.setAttribute('vector-effect', 'non-scaling-stroke');
+    minimap_view.setAttribute('pointer-events', 'none');
+    minimap.appendChild(minimap_view);
+
+    minimap.addEventListener('mousedown', function (evt) {
+        evt.stopPropagation();
+        minimap_drag = true;
+        centerOnMinimap(evt);
+    });
+    minimap.addEventListener('click', function (evt) {
+        evt.stopPropagation();
+    });
+    document.documentElement.appendChild(minimap);
+}
+
+//
+// Keep the minimap the same size, in the same corner of the iframe, and
+// outline the part of the graph in view.
+//
+function updateMinimap() {
+    var scale = view.width / window.innerWidth;
+    minimap.setAttribute('x',
+        view.x + view.width - (minimap_width + 10) * scale);
+    minimap.setAttribute('y',
+        view.y + view.height - (minimap_height + 10) * scale);
+    minimap.setAttribute('width', minimap_width * scale);
+    minimap.setAttribute('height', minimap_height * scale);
+
+    minimap_view.setAttribute('x', view.x);
+    minimap_view.setAttribute('y', view.y);
+    minimap_view.setAttribute('width', view.width);
+    minimap_view.setAttribute('height', view.height);
+}
+
+//
+// Pan so that the point under the pointer on the minimap is in the middle of
+// the iframe.
+//
+function centerOnMinimap(evt) {
+    var p = toGraph(evt.clientX, evt.clientY);
+    var mx = parseFloat(minimap.getAttribute('x'));
+    var my = parseFloat(minimap.getAttribute('y'));
+    var mw = parseFloat(minimap.getAttribute('width'));
+    var mh = parseFloat(minimap.getAttribute('height'));
+    var x = base.x + (p.x - mx) / mw * base.width;
+    var y = base.y + (p.y - my) / mh * base.height;
+    setView({x: x - view.width / 2, y: y - view.height / 2,
+        width: view.width, height: view.height});
+}
+
+//
+// Keyboard navigation.  The arrow keys move the focus from the focused vertex
+// to one of its parents or children, or to the previous or next vertex in the
+// same rank, skipping collapsed vertices.  Which keys do which depends on the
+// orientation of the layout.  Enter (or space) selects the focused vertex and
+// / jumps to the search box.
+//
+function initKeyboard() {
+    var parentDoc = window.parent.document;
+    var moves;
+    if (document.documentElement.getAttribute('data-orientation') === 'tb') {
+        moves = {ArrowUp: 'data-parents', ArrowDown: 'data-children',
+            ArrowLeft: 'data-prev', ArrowRight: 'data-next'};
+    } else {
+        moves = {ArrowLeft: 'data-parents', ArrowRight: 'data-children',
+            ArrowUp: 'data-prev', ArrowDown: 'data-next'};
+    }
+
+    document.addEventListener('keydown', function (evt) {
+        if (evt.key === '/') {
+            evt.preventDefault();
+            parentDoc.getElementById('search').focus();
+            return;
+        }
+
+        var group = document.activeElement;
+        var focused = group !== null && group.classList !== undefined &&
+            group.classList.contains('vertex');
+        if (focused && (evt.key === 'Enter' || evt.key === ' ')) {
+            evt.preventDefault();
+            selectVertex(group);
+            return;
+        }
+        if (!moves.hasOwnProperty(evt.key)) {
+            return;
+        }
+        evt.preventDefault();
+
+        //
+        // If no vertex has the focus yet, start from the selected one, or
+        // failing that the first one.
+        //
+        var next;
+        if (focused) {
+            next = adjacentVertex(group, moves[evt.key]);
+        } else {
+            next = document.querySelector('g.vertex.selected') ||
+                document.querySelector('g.vertex');
+        }
+        if (next !== null) {
+            next.focus({preventScroll: true});
+            ensureVisible(next);
+        }
+    });
+
+    parentDoc.addEventListener('keydown', function (evt) {
+        var tag = evt.target.tagName;
+        if (evt.key === '/' && tag !== 'INPUT' && tag !== 'TEXTAREA') {
+            evt.preventDefault();
+            parentDoc.getElementById('search').focus();
+        }
+    });
+}
+
+//
+// Return the first visible vertex named by the specified adjacency attribute
+// of a vertex, or null if there isn't one.  The previous and next vertices in
+// a rank are followed along the rank until a visible one is found.
+//
+function adjacentVertex(group, attr) {
+    var vtx;
+    if (attr === 'data-prev' || attr === 'data-next') {
+        var id = group.getAttribute(attr);
+        while (id !== null) {
+            vtx = document.getElementById(id);
+            if (vtx.getAttribute('display') !== 'none') {
+                return vtx;
+            }
+            id = vtx.getAttribute(attr);
+        }
+        return null;
+    }
+
+    var ids = group.getAttribute(attr).split(' ');
+    for (let i = 0; i < ids.length; i++) {
+        vtx = document.getElementById(ids[i]);
+        if (vtx !== null && vtx.getAttribute('display') !== 'none') {
+            return vtx;
+        }
+    }
+    return null;
+}
+
+//
+// Pan to the specified element if it isn't entirely in view.
+//
+function ensureVisible(elem) {
+    var bb = elem.getBBox();
+    if (bb.x < view.x || bb.y < view.y ||
+        bb.x + bb.width > view.x + view.width ||
+        bb.y + bb.height > view.y + view.height) {
+        centerOn(elem);
+    }
+}
+
+//
+// Deep links: a URL fragment of the form #KEY=VALUE (e.g. #fmri=... or
+// #serial=...) selects the vertex whose KEY attribute is VALUE.  Selecting a
+// vertex updates the fragment, so that the URL of the page can be pasted
+// somewhere to link back to it.
+//
+var fragment_aliases = {'serial': 'serial-number'};
+
+function selectFromFragment() {
+    var hash = window.parent.location.hash.substring(1);
+    var sep = hash.indexOf('=');
+    if (sep === -1) {
+        return;
+    }
+
+    var key;
+    var value;
+    try {
+        key = decodeURIComponent(hash.substring(0, sep));
+        value = decodeURIComponent(hash.substring(sep + 1));
+    } catch (e) {
+        console.log('malformed URL fragment: ' + hash);
+        return;
+    }
+    if (fragment_aliases.hasOwnProperty(key)) {
+        key = fragment_aliases[key];
+    }
+
+    var vertices = document.querySelectorAll('g.vertex');
+    for (let i = 0; i < vertices.length; i++) {
+        if (vertexProp(vertices[i], key) === value) {
+            revealVertex(vertices[i]);
+            selectVertex(vertices[i]);
+            centerOn(vertices[i]);
+            return;
+        }
+    }
+    console.log('no vertex with ' + key + '=' + value);
+}
+
+//
+// Point the URL fragment at the selected vertex.  Disks are linked to by serial
+// number, which is what gets quoted in tickets, and everything else by FMRI.
+//
+function updateFragment(group) {
+    var serial = vertexProp(group, 'serial-number');
+    var fragment = serial !== null ?
+        'serial=' + encodeURIComponent(serial) :
+        'fmri=' + encodeURIComponent(vertexProp(group, 'fmri'));
+    window.parent.history.replaceState(null, '', '#' + fragment);
+}
+
+//
+// Return the ids of the specified vertex and of every vertex reached from it
+// by repeatedly following the ids in the named attribute (data-parents or
+// data-children).
+//
+function reachable(group, attr) {
+    var seen = new Set([group.id]);
+    var stack = [group];
+    while (stack.length > 0) {
+        var ids = stack.pop().getAttribute(attr);
+        if (!ids) {
+            continue;
+        }
+        for (const id of ids.split(' ')) {
+            var next = document.getElementById(id);
+            if (!seen.has(id) && next !== null) {
+                seen.add(id);
+                stack.push(next);
+            }
+        }
+    }
+    return seen;
+}
+
+//
+// Highlight every vertex and edge on the initiator to target paths through the
+// specified vertex, which are made up of the vertices upstream and downstream
+// of it.  An edge lies on one of those paths if both its ends are upstream of
+// (or are) the vertex, or both are downstream of (or are) it.
+//
+function highlightPaths(group) {
+    var upstream = reachable(group, 'data-parents');
+    var downstream = reachable(group, 'data-children');
+
+    var vertices = document.querySelectorAll('g.vertex');
+    for (let i = 0; i < vertices.length; i++) {
+        var id = vertices[i].id;
+        if (vertices[i] === group) {
+            vertices[i].classList.add('selected');
+        } else {
+            vertices[i].classList.remove('selected');
+        }
+        if (upstream.has(id) || downstream.has(id)) {
+            vertices[i].classList.add('on-path');
+        } else {
+            vertices[i].classList.remove('on-path');
+        }
+    }
+
+    var edges = document.querySelectorAll('.edge[data-dst]');
+    for (let i = 0; i < edges.length; i++) {
+        var src = edges[i].getAttribute('data-src');
+        var dst = edges[i].getAttribute('data-dst');
+        if ((upstream.has(src) && upstream.has(dst)) ||
+            (downstream.has(src) && downstream.has(dst))) {
+            edges[i].classList.add('on-path');
+        } else {
+            edges[i].classList.remove('on-path');
+        }
+    }
+}
+
+var matches = [];
+var current_match = -1;
+
+//
+// Find the vertices with a property (or FMRI) that contains the search text
+// (ignoring case), highlight them and dim everything else.
+//
+function runSearch(text) {
+    var query = text.trim().toLowerCase();
+    var vertices = document.querySelectorAll('g.vertex');
+
+    matches = [];
+    current_match = -1;
+    for (let i = 0; i < vertices.length; i++) {
+        var found = false;
+        var values = vertexValues(vertices[i]);
+        for (let j = 0; query !== '' && j < values.length && !found; j++) {
+            if (values[j].toLowerCase().indexOf(query) !== -1) {
+                found = true;
+            }
+        }
+        vertices[i].classList.remove('current');
+        if (found) {
+            vertices[i].classList.add('match');
+            matches.push(vertices[i]);
+        } else {
+            vertices[i].classList.remove('match');
+        }
+    }
+
+    if (query === '') {
+        document.documentElement.classList.remove('searching');
+    } else {
+        document.documentElement.classList.add('searching');
+    }
+    updateSearchCount();
+}
+
+//
+// Select the next (dir = 1) or previous (dir = -1) search match, expanding any
+// collapsed subtree it is in and scrolling it into view.
+//
+function cycleMatch(dir) {
+    if (matches.length === 0) {
+        return;
+    }
+    if (current_match === -1) {
+        current_match = dir > 0 ? 0 : matches.length - 1;
+    } else {
+        matches[current_match].classList.remove('current');
+        current_match = (current_match + dir + matches.length) %
+            matches.length;
+    }
+
+    var group = matches[current_match];
+    group.classList.add('current');
+    revealVertex(group);
+    selectVertex(group);
+    centerOn(group);
+    updateSearchCount();
+}
+
+function updateSearchCount() {
+    var count = window.parent.document.getElementById('search-count');
+    if (matches.length === 0) {
+        count.textContent = document.documentElement.classList.contains(
+            'searching') ? 'No matches' : '';
+    } else if (current_match === -1) {
+        count.textContent = matches.length + ' matches';
+    } else {
+        count.textContent = (current_match + 1) + ' of ' + matches.length;
+    }
+}
+
+//
+// Collapse or expand the subtree downstream of an expander, when its toggle
+// button or its summary node is clicked.
+//
+function toggleSubtree(evt) { // eslint-disable-line no-unused-vars
+    evt.stopPropagation();
+    var id = evt.currentTarget.getAttribute('data-expander');
+    document.getElementById(id).classList.toggle('collapsed');
+    refreshCollapsed();
+}
+
+//
+// Expand any collapsed subtrees that the specified vertex is hidden in.
+//
+function revealVertex(group) {
+    var collapsed = document.querySelectorAll('g.vertex.collapsed');
+    for (let i = 0; i < collapsed.length; i++) {
+        var members = collapsed[i].getAttribute('data-subtree').split(' ');
+        if (members.indexOf(group.id) !== -1) {
+            collapsed[i].classList.remove('collapsed');
+        }
+    }
+    refreshCollapsed();
+}
+
+//
+// Hide the vertices and edges in the subtrees of all collapsed expanders, and
+// the boxes around enclosures whose disks are all hidden, and show the summary
+// nodes standing in for them.  Subtrees can be nested (with
+// cascaded expanders), so a summary is only shown if its expander is itself
+// visible.
+//
+function refreshCollapsed() {
+    var hidden = new Set();
+    var collapsed = document.querySelectorAll('g.vertex.collapsed');
+    for (let i = 0; i < collapsed.length; i++) {
+        var members = collapsed[i].getAttribute('data-subtree').split(' ');
+        for (let j = 0; j < members.length; j++) {
+            hidden.add(members[j]);
+        }
+    }
+
+    var vertices = document.querySelectorAll('g.vertex');
+    for (let i = 0; i < vertices.length; i++) {
+        vertices[i].setAttribute('display',
+            hidden.has(vertices[i].id) ? 'none' : 'inline');
+    }
+
+    var edges = document.querySelectorAll('[data-src]');
+    for (let i = 0; i < edges.length; i++) {
+        var src = edges[i].getAttribute('data-src');
+        var dst = edges[i].getAttribute('data-dst');
+        var visible = !hidden.has(src) &&
+            (dst === null || !hidden.has(dst));
+        edges[i].setAttribute('display', visible ? 'inline' : 'none');
+    }
+
+    var enclosures = document.querySelectorAll('g.enclosure');
+    for (let i = 0; i < enclosures.length; i++) {
+        var targets = enclosures[i].getAttribute('data-members').split(' ');
+        var shown = targets.some(function (id) {
+            return !hidden.has(id);
+        });
+        enclosures[i].setAttribute('display', shown ? 'inline' : 'none');
+    }
+
+    var summaries = document.querySelectorAll('g.summary');
+    for (let i = 0; i < summaries.length; i++) {
+        var id = summaries[i].getAttribute('data-expander');
+        var expander = document.getElementById(id);
+        var expanded = hidden.has(id) ||
+            !expander.classList.contains('collapsed');
+        summaries[i].setAttribute('display', expanded ? 'none' : 'inline');
+    }
+
+    var toggles = document.querySelectorAll('g.toggle');
+    for (let i = 0; i < toggles.length; i++) {
+        var exp = document.getElementById(
+            toggles[i].getAttribute('data-expander'));
+        toggles[i].getElementsByTagName('text')[0].textContent =
+            exp.classList.contains('collapsed') ? '+' : '\u2212';
+    }
+}
+
+//
+// Return the value of the named property of the vertex drawn by the specified
+// group, or null if it doesn't have that property.  If more than one property
+// group defines it, the first group wins.  "fmri" and "name" are the FMRI and
+// type of the vertex.
+//
+function vertexProp(group, prop) {
+    var fmri = group.getAttribute('data-fmri');
+    var vtx = viewer_data.vertices[fmri];
+    if (prop === 'fmri') {
+        return fmri;
+    } else if (vtx === undefined) {
+        return null;
+    } else if (prop === 'name') {
+        return vtx.name;
+    }
+    for (const pg of vtx.propgroups) {
+        if (pg.properties.hasOwnProperty(prop)) {
+            return pg.properties[prop];
+        }
+    }
+    return null;
+}
+
+//
+// Return the property groups of the vertex drawn by the specified group, in
+// the order they appear in the snapshot.
+//
+function vertexPropGroups(group) {
+    var vtx = viewer_data.vertices[group.getAttribute('data-fmri')];
+    return vtx === undefined ? [] : vtx.propgroups;
+}
+
+//
+// Return the FMRI, type and property values of the vertex drawn by the
+// specified group.  Searching only matches these, never the attributes of the
+// group, which also carry the ids of neighbouring vertices (data-parents,
+// data-children, data-prev, data-next) and accessibility text (aria-label,
+// role) that would give false matches.
+//
+function vertexValues(group) {
+    var fmri = group.getAttribute('data-fmri');
+    var vtx = viewer_data.vertices[fmri];
+    var values = [fmri];
+    if (vtx !== undefined) {
+        values.push(vtx.name);
+        for (const pg of vtx.propgroups) {
+            for (const prop of Object.keys(pg.properties)) {
+                values.push(pg.properties[prop]);
+            }
+        }
+    }
+    return values;
+}
+
+//
+// Highlight the chassis bay for the specified location, if there is one.
+//
+function highlightBay(location) {
+    for (let i = 0; i < bays.length; i++) {
+        if (bays[i].getAttribute('data-location') === location) {
+            bays[i].classList.add('bay-selected');
+        } else {
+            bays[i].classList.remove('bay-selected');
+        }
+    }
+}
+
+//
+// Select the vertex in the specified slot location.  If the slot is empty,
+// just highlight its bay.
+//
+function selectLocation(location) {
+    var groups = document.querySelectorAll('g.vertex');
+    for (let i = 0; i < groups.length; i++) {
+        if (vertexProp(groups[i], 'location') === location) {
+            revealVertex(groups[i]);
+            selectVertex(groups[i]);
+            centerOn(groups[i]);
+            return;
+        }
+    }
+    highlightBay(location);
+}
+
 var link_rate_strings = [
     'Unknown',
     'Disabled',
@@ -64,13 +836,57 @@ var link_rate_strings = [
 ];
 
 //
-// When a graph vertex is clicked in the SVG, highlight the clicked vertex and
-// and populate the info panel on the left side with the properties of that
-// vertex.
+// Append a cell containing the specified text to a table row.  Values come
+// from the topology snapshot, so they are always added as text, never as HTML.
+//
+function addCell(row, text, bold) {
+    var cell = row.insertCell(-1);
+    cell.textContent = text;
+    if (bold) {
+        cell.style.fontWeight = 'bold';
+    }
+    return cell;
+}
+
+//
+// Append a row for the named property to the Node Information table.
+//
+function addInfoRow(nodeinfo, prop, value) {
+    //
+    // The value for hc-fmri can be quite long, so to make it fit better in
+    // the info panel, we strip out the authority portion of the fmri.
+    //
+    if (prop === 'hc-fmri') {
+        let end_auth = value.indexOf('/', 6);
+        if (end_auth !== -1) {
+            value = 'hc://' + value.substring(end_auth);
+        }
+    }
+    var row = nodeinfo.insertRow(-1);
+    addCell(row, prop, true);
+    var valuecell = addCell(row, value, false);
+    valuecell.colSpan = 4;
+}
+
+//
+// When a graph vertex is clicked in the SVG, select it.
 //
 function showInfo(evt) { // eslint-disable-line no-unused-vars
+    var group = evt.currentTarget;
+    group.focus({preventScroll: true});
+    selectVertex(group);
+}
+
+//
+// Highlight the selected vertex and its chassis bay, and populate the info
+// panel on the left side with the properties of that vertex.
+//
+function selectVertex(group) {
     var parentDoc = window.parent.document;
 
+    highlightPaths(group);
+    updateFragment(group);
+
     //
     // Iterate through the DOM <img> elements, which represent the graph
     // vertices and set the fill color to white.
@@ -84,9 +900,13 @@ function showInfo(evt) { // eslint-disable-line no-unused-vars
     // Highlight the vertex that was clicked by setting the a filter on the
     // associated image element.
     //
-    var img = evt.target.parentElement.getElementsByTagName('image');
+    var img = group.getElementsByTagName('image');
     img[0].setAttribute('filter', 'url(#linear)');
 
+    if (standalone) {
+        return;
+    }
+
     // Clear the Node Information table
     var nodeinfo = parentDoc.getElementById('nodeinfo');
     var numrows = nodeinfo.rows.length;
@@ -112,63 +932,42 @@ function showInfo(evt) { // eslint-disable-line no-unused-vars
         errinfo.deleteRow(-1);
     }
 
-    var group = evt.target.parentElement;
     var link_rate_props = ['max-link-rate', 'negotiated-link-rate'];
     var link_err_props = ['invalid-dword', 'running-disparity-error',
         'loss-dword-sync', 'reset-problem-count'];
-    var props;
-    var name = group.getAttribute('name');
-
-    if (name === 'initiator') {
-        props = ['fmri', 'hc-fmri', 'dev-fmri', 'devfs-path', 'name', 'manufacturer',
-            'model', 'location'];
-    } else if (name === 'port') {
-        props = ['fmri', 'name', 'sas-port-type', 'local-sas-address',
-            'attached-sas-address'];
-    } else if (name === 'expander') {
-        props = ['fmri', 'name', 'devfs-path', 'manufacturer', 'model'];
-    } else if (name === 'target') {
-        props = ['fmri', 'hc-fmri', 'dev-fmri', 'name', 'logical-disk', 'manufacturer',
-            'model', 'serial-number', 'location'];
-    }
-
-    for (const prop of props) {
-        let value = group.getAttribute(prop);
-        //
-        // The value for hc-fmri can be quite long, so to make it fit better in
-        // the info panel, we strip out the authority portion of the fmri.
-        //
-        if (prop === 'hc-fmri') {
-            let end_auth = value.indexOf('/', 6);
-            if (end_auth !== -1) {
-                value = 'hc://' + value.substring(end_auth);
-            }
+    var name = vertexProp(group, 'name');
+
+    //
+    // The display schema from build_svg lists the properties to show for
+    // each type of vertex.  Everything is shown for types it doesn't know
+    // about, or if "show all properties" is checked.
+    //
+    var schema = viewer_data.schema[name];
+    var show_all = parentDoc.getElementById('show-all').checked ||
+        schema === undefined;
+
+    //
+    // The FMRI and type of the vertex come first, followed by its properties
+    // under the name of the property group that defines them.
+    //
+    addInfoRow(nodeinfo, 'fmri', vertexProp(group, 'fmri'));
+    addInfoRow(nodeinfo, 'name', name);
+    for (const pg of vertexPropGroups(group)) {
+        let shown = show_all ? Object.keys(pg.properties) :
+            schema.filter(function (prop) {
+            return pg.properties.hasOwnProperty(prop);
+        });
+        if (shown.length === 0) {
+            continue;
         }
-        var row = nodeinfo.insertRow(-1);
-        var fieldcell = row.insertCell(-1);
-        fieldcell.innerHTML = prop.bold();
-        var valuecell = row.insertCell(-1);
-        valuecell.colSpan = 4;
-        valuecell.innerHTML = value;
-    }
-    let location = group.getAttribute('location');
-    if (chassis_images.includes(product_id)) {
-        if (location !== null) {
-            location = location.replace(/ /g, '-');
-            let img_file = product_id + '-' + location;
-            front_image_cell.innerHTML = '<center><img src=\'assets/' +
-                product_id + '/' + img_file + '-front.png\'></img></center>';
-            rear_image_cell.innerHTML = '<center><img src=\'assets/' +
-                product_id + '/' + img_file + '-rear.png\'></img></center>';
-        } else {
-            let img_file = product_id + '-front.png';
-            front_image_cell.innerHTML = '<center><img src=\'assets/' +
-                product_id + '/' + img_file + '\'></img></center>';
-            img_file = product_id + '-rear.png';
-            rear_image_cell.innerHTML = '<center><img src=\'assets/' +
-                product_id + '/' + img_file + '\'></img></center>';
+        let pgrow = nodeinfo.insertRow(-1);
+        pgrow.className = 'propgroup';
+        addCell(pgrow, pg.name, true).colSpan = 2;
+        for (const prop of shown) {
+            addInfoRow(nodeinfo, prop, pg.properties[prop]);
         }
     }
+    highlightBay(vertexProp(group, 'location'));
 
     if (name === 'port') {
         //
@@ -176,7 +975,7 @@ function showInfo(evt) { // eslint-disable-line no-unused-vars
         // start_phy and end_phy fields from the authority portion of this
         // node's sas-scheme FMRI.
         //
-        var fmri = group.getAttribute('fmri');
+        var fmri = vertexProp(group, 'fmri');
         /* JSSTYLED */
         var regex = /start-phy=(\d+):end-phy=(\d+)/g;
         var match = regex.exec(fmri);
@@ -191,7 +990,7 @@ function showInfo(evt) { // eslint-disable-line no-unused-vars
             };
         }
         for (const prop of link_rate_props) {
-            let value = group.getAttribute(prop);
+            let value = vertexProp(group, prop);
             if (value === undefined || value === null) {
                 return;
             }
@@ -206,19 +1005,15 @@ function showInfo(evt) { // eslint-disable-line no-unused-vars
         ratetable.hidden = false;
 
         var hdrrow = rateinfo.insertRow(-1);
-        var hdrcell = hdrrow.insertCell(-1);
-        hdrcell.innerHTML = 'PHY #'.bold();
+        addCell(hdrrow, 'PHY #', true);
         for (const prop of link_rate_props) {
-            hdrcell = hdrrow.insertCell(-1);
-            hdrcell.innerHTML = prop.bold();
+            addCell(hdrrow, prop, true);
         }
         for (let i = 0; i < num_phys; i++) {
             let raterow = rateinfo.insertRow(-1);
-            let ratecell = raterow.insertCell(-1);
-            ratecell.innerHTML = (+start_phy + +i);
+            addCell(raterow, +start_phy + +i, false);
             for (const prop of link_rate_props) {
-                ratecell = raterow.insertCell(-1);
-                ratecell.innerHTML = phys[i][prop];
+                addCell(raterow, phys[i][prop], false);
             }
         }
 
@@ -232,7 +1027,7 @@ function showInfo(evt) { // eslint-disable-line no-unused-vars
             };
         }
         for (const prop of link_err_props) {
-            var value = group.getAttribute(prop);
+            var value = vertexProp(group, prop);
             if (value === undefined || value === null) {
                 return;
             }
@@ -246,19 +1041,15 @@ function showInfo(evt) { // eslint-disable-line no-unused-vars
         errtable.hidden = false;
 
         hdrrow = errinfo.insertRow(-1);
-        hdrcell = hdrrow.insertCell(-1);
-        hdrcell.innerHTML = 'PHY #'.bold();
+        addCell(hdrrow, 'PHY #', true);
         for (const prop of link_err_props) {
-            hdrcell = hdrrow.insertCell(-1);
-            hdrcell.innerHTML = prop.bold();
+            addCell(hdrrow, prop, true);
         }
         for (let i = 0; i < num_phys; i++) {
             var errrow = errinfo.insertRow(-1);
-            var errcell = errrow.insertCell(-1);
-            errcell.innerHTML = (+start_phy + +i);
+            addCell(errrow, +start_phy + +i, false);
             for (const prop of link_err_props) {
-                errcell = errrow.insertCell(-1);
-                errcell.innerHTML = parseInt(phys[i][prop], 16);
+                addCell(errrow, parseInt(phys[i][prop], 16), false);
             }
         }
     }
@@ -0,0 +1,448 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// Serve mode renders the webpage in memory and serves it, along with the
+// images it references, over HTTP.  Browsers treat every file:// URL as a
+// separate origin, which stops the SVG from reaching into the page that
+// embeds it (through window.parent.document); served from one HTTP origin,
+// the two can talk to each other.  Optionally, the snapshot is watched and
+// the webpage re-rendered whenever it changes, and pages open in a browser
+// reload themselves to show it.
+//
+use log::debug;
+
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::{Assets, Config, SimpleError, Webpage, HTML_FILE, SVG_FILE};
+
+//
+// How often the snapshot is checked for changes when watching it
+//
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+//
+// Number of requests handled at once; further connections wait to be
+// accepted until one of them is done.
+//
+const WORKERS: usize = 8;
+
+//
+// How long a client can take to send the next part of its request, or to
+// take the next part of the response, before the connection is dropped
+//
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+//
+// Most bytes read of the request line and headers together
+//
+const MAX_REQUEST_HEAD: u64 = 16 * 1024;
+
+//
+// Path the generation of the webpage is served at when watching the snapshot
+//
+const GENERATION_PATH: &str = "generation";
+
+//
+// The webpage being served.  When the snapshot is watched, the generation
+// goes up by one every time the webpage is re-rendered; pages poll it to
+// find out when to reload.
+//
+struct Served {
+    webpage: Webpage,
+    generation: Option<u64>,
+}
+
+//
+// Loads the snapshot and renders the webpage from it.
+//
+fn render(config: &Config) -> Result<Webpage, Box<dyn Error>> {
+    let mut digraph = super::load_digraph(config)?;
+    super::render_webpage(config, &mut digraph)
+}
+
+//
+// Returns the modification time of the snapshot, if it can be determined.
+//
+fn modified(xml_path: &str) -> Option<SystemTime> {
+    fs::metadata(xml_path).and_then(|meta| meta.modified()).ok()
+}
+
+//
+// Polls the snapshot for changes, re-rendering the webpage each time it's
+// modified.  A snapshot that can't be rendered, which may just be one that's
+// still being written, leaves the last webpage in place until the next
+// change.
+//
+fn watch(config: Config, served: Arc<RwLock<Served>>) {
+    let mut last_modified = modified(&config.xml_path);
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let now_modified = modified(&config.xml_path);
+        if now_modified.is_none() || now_modified == last_modified {
+            continue;
+        }
+        last_modified = now_modified;
+
+        match render(&config) {
+            Ok(rendered) => {
+                let mut served = served.write().unwrap();
+                served.webpage = rendered;
+                served.generation = served.generation.map(|generation| generation + 1);
+                eprintln!("{} changed, webpage re-rendered", config.xml_path);
+            }
+            Err(e) => eprintln!("{} changed, but couldn't be rendered: {}", config.xml_path, e),
+        }
+    }
+}
+
+//
+// Adds a script to the HTML page that polls the generation of the webpage,
+// reloading the page once it's no longer the one the page was served at.
+// Errors, such as the server having been stopped, are ignored.
+//
+fn reloading_html(html: &str, generation: u64) -> String {
+    let script = format!(
+        "<script>\n\
+         setInterval(function () {{\n\
+         fetch('generation').then(function (response) {{\n\
+         return response.ok ? response.text() : '{0}';\n\
+         }}).then(function (generation) {{\n\
+         if (generation !== '{0}') {{\n\
+         location.reload();\n\
+         }}\n\
+         }}).catch(function () {{}});\n\
+         }}, {1});\n\
+         </script>\n</body>",
+        generation,
+        WATCH_INTERVAL.as_millis()
+    );
+    html.replacen("</body>", &script, 1)
+}
+
+//
+// Decodes the percent-encoded characters in the path of a request.  Returns
+// None if an escape is truncated or not hexadecimal, or if the decoded path
+// isn't UTF-8.
+//
+fn decode_path(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+//
+// Returns the path, relative to the assets directory, of the asset that the
+// specified (decoded) request path refers to.  Only paths made up entirely of
+// file and directory names are accepted, so that one with .. in it, or an
+// absolute one, can't be used to read anything outside of the directory.
+//
+fn asset_path(path: &str) -> Option<&str> {
+    let asset = path.strip_prefix("assets/").filter(|asset| !asset.is_empty())?;
+    let inside = Path::new(asset)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if inside {
+        Some(asset)
+    } else {
+        None
+    }
+}
+
+//
+// Returns the MIME type of the file at the specified path, going by its
+// extension.
+//
+fn content_type(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" => "text/html; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "json" => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+//
+// Writes a response with the specified status and body, leaving out the body
+// in response to a HEAD request.
+//
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+    head: bool,
+) -> Result<(), Box<dyn Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if !head {
+        stream.write_all(body)?;
+    }
+    stream.flush()?;
+    Ok(())
+}
+
+//
+// Handles a single request: the HTML page and the SVG are served from the
+// webpage rendered in memory, the generation of the webpage (if the snapshot
+// is watched) from /generation, and anything under assets/ from the assets
+// directory.  Only GET and HEAD are supported, and the headers are read but
+// otherwise ignored.
+//
+fn handle(
+    mut stream: TcpStream,
+    served: &RwLock<Served>,
+    asset_dir: &str,
+) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?).take(MAX_REQUEST_HEAD);
+    reader.read_line(&mut request_line)?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+    if reader.limit() == 0 {
+        return respond(
+            &mut stream,
+            "431 Request Header Fields Too Large",
+            "text/plain",
+            b"request too large\n",
+            false,
+        );
+    }
+
+    let mut fields = request_line.split_whitespace();
+    let (method, target) = match (fields.next(), fields.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return respond(&mut stream, "400 Bad Request", "text/plain", b"bad request\n", false),
+    };
+    debug!("{} {}", method, target);
+    let head = match method {
+        "GET" => false,
+        "HEAD" => true,
+        _ => {
+            return respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                b"method not allowed\n",
+                false,
+            )
+        }
+    };
+
+    let path = target.split(&['?', '#'][..]).next().unwrap_or("");
+    let path = match decode_path(path) {
+        Some(path) => path,
+        None => return respond(&mut stream, "400 Bad Request", "text/plain", b"bad request\n", head),
+    };
+    let path = path.trim_start_matches('/');
+
+    if path.is_empty() || path == HTML_FILE {
+        let html = {
+            let served = served.read().unwrap();
+            match served.generation {
+                Some(generation) => reloading_html(&served.webpage.html, generation),
+                None => served.webpage.html.clone(),
+            }
+        };
+        return respond(&mut stream, "200 OK", content_type(HTML_FILE), html.as_bytes(), head);
+    }
+    if path == SVG_FILE {
+        let svg = served.read().unwrap().webpage.svg.clone();
+        return respond(&mut stream, "200 OK", content_type(SVG_FILE), svg.as_bytes(), head);
+    }
+    if path == GENERATION_PATH {
+        if let Some(generation) = served.read().unwrap().generation {
+            let body = generation.to_string();
+            return respond(&mut stream, "200 OK", "text/plain", body.as_bytes(), head);
+        }
+    }
+
+    if let Some(asset) = asset_path(path) {
+        if let Ok(contents) = fs::read(Path::new(asset_dir).join(asset)) {
+            return respond(&mut stream, "200 OK", content_type(asset), &contents, head);
+        }
+    }
+    respond(&mut stream, "404 Not Found", "text/plain", b"not found\n", head)
+}
+
+//
+// Renders the webpage and serves it on the specified address until the
+// process is killed.  If asked to watch the snapshot, the webpage is
+// re-rendered whenever the snapshot changes and open pages reload themselves.
+// Connections are handed to a fixed number of worker threads.
+//
+pub fn serve(config: &Config, address: &str, watch_xml: bool) -> Result<(), Box<dyn Error>> {
+    if watch_xml && config.xml_path == "-" {
+        return Err(Box::new(SimpleError(
+            "standard input can't be watched for changes".to_string(),
+        )));
+    }
+
+    let mut config = config.clone();
+    config.shared_assets = None;
+    let asset_dir = Assets::new(&config, false)?.src_dir;
+    let served = Arc::new(RwLock::new(Served {
+        webpage: render(&config)?,
+        generation: if watch_xml { Some(0) } else { None },
+    }));
+
+    let listener = TcpListener::bind(address)?;
+    eprintln!(
+        "Serving {} at http://{}/",
+        config.xml_path,
+        listener.local_addr()?
+    );
+
+    if watch_xml {
+        let config = config.clone();
+        let served = Arc::clone(&served);
+        thread::spawn(move || watch(config, served));
+    }
+
+    //
+    // Each worker takes the next connection off the queue until the queue is
+    // closed.  The queue only holds as many connections as there are workers,
+    // after which accepting waits for it to drain.
+    //
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(WORKERS);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let asset_dir = Arc::new(asset_dir);
+    for _ in 0..WORKERS {
+        let receiver = Arc::clone(&receiver);
+        let served = Arc::clone(&served);
+        let asset_dir = Arc::clone(&asset_dir);
+        thread::spawn(move || loop {
+            let stream = match receiver.lock().unwrap().recv() {
+                Ok(stream) => stream,
+                Err(_) => break,
+            };
+            if let Err(e) = handle(stream, &served, &asset_dir) {
+                debug!("failed to handle request: {}", e);
+            }
+        });
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => sender.send(stream)?,
+            Err(e) => debug!("failed to accept connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{asset_path, decode_path, reloading_html};
+
+    #[test]
+    fn decoding() {
+        assert_eq!(decode_path("/assets/a%20b.png").unwrap(), "/assets/a b.png");
+        assert_eq!(decode_path("%2e%2E/%2F").unwrap(), "..//");
+        assert_eq!(decode_path("caf%C3%A9").unwrap(), "caf\u{e9}");
+
+        // truncated and malformed escapes
+        for path in &["%", "a%2", "%2", "%zz", "%+1", "%-1", "% 1"] {
+            assert_eq!(decode_path(path), None, "{}", path);
+        }
+
+        // escapes that decode to something other than UTF-8
+        assert_eq!(decode_path("%ff"), None);
+        assert_eq!(decode_path("%C3"), None);
+    }
+
+    //
+    // Only paths under assets/ that stay inside it, after decoding, are
+    // mapped to assets.
+    //
+    #[test]
+    fn assets_are_contained() {
+        assert_eq!(asset_path("assets/sas-disk.png"), Some("sas-disk.png"));
+        assert_eq!(asset_path("assets/chassis/front.png"), Some("chassis/front.png"));
+
+        for path in &[
+            "assets/../Cargo.toml",
+            "assets/chassis/../../Cargo.toml",
+            "assets/..",
+            "assets//etc/passwd",
+            "assets/",
+            "assets/.",
+            "assets",
+            "images/sas-disk.png",
+            "../assets/sas-disk.png",
+        ] {
+            assert_eq!(asset_path(path), None, "{}", path);
+        }
+
+        for path in &[
+            "assets/%2e%2e/Cargo.toml",
+            "assets%2F..%2FCargo.toml",
+            "assets/%2F%2Fetc/passwd",
+        ] {
+            let decoded = decode_path(path).unwrap();
+            assert_eq!(asset_path(&decoded), None, "{}", path);
+        }
+    }
+
+    //
+    // The reload script goes at the end of the body and compares the polled
+    // generation with the one the page was served at.
+    //
+    #[test]
+    fn reload_script() {
+        let html = reloading_html("<html><body><p>x</p></body></html>\n", 42);
+        assert!(html.starts_with("<html><body><p>x</p><script>\n"), "{}", html);
+        assert!(html.ends_with("</script>\n</body></html>\n"), "{}", html);
+        assert!(html.contains("fetch('generation')"), "{}", html);
+        assert!(html.contains("if (generation !== '42') {"), "{}", html);
+        assert!(html.contains("}, 1000);"), "{}", html);
+    }
+}
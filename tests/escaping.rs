@@ -0,0 +1,169 @@
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+// Copyright 2023 MNX Cloud, Inc.
+//
+
+//
+// Renders a topology snapshot whose property values try to inject markup and
+// script into the viewer (tests/data/hostile.xml) and checks that every one of
+// them comes out of the generated SVG as plain text.  Host and vertex
+// properties are embedded as JSON, so that is decoded too.  The self-contained
+// HTML document and the chassis view are checked by the unit tests in lib.rs.
+//
+extern crate sastopo2svg;
+extern crate serde_json;
+extern crate xml;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use serde_json::Value;
+use xml::reader::{EventReader, XmlEvent};
+
+//
+// The values in the corpus, as they are after the snapshot has been parsed.
+//
+const HOSTILE_VALUES: &[&str] = &[
+    "\"><script>alert(1)</script>",
+    "' onmouseover='alert(2)",
+    "<img src=x onerror=alert(3)>",
+    "]]><script>alert(4)</script><![CDATA[",
+    "&lt;b&gt;already-escaped&amp;",
+    "</title><script>alert(6)</script>",
+    "<script>alert(7)</script>",
+    "Disk \"0\" & 'friends'",
+    "javascript:alert(8)",
+    "x onload=\"alert(9)\"",
+];
+
+//
+// Properties whose names are those of attributes that would run script, or
+// that the viewer sets on the vertex groups itself, with the values they have
+// in the corpus.  They must only ever come out as data, never as attributes.
+//
+const HOSTILE_PROPERTIES: &[(&str, &str)] = &[
+    ("onclick", "alert(10)"),
+    ("onmouseover", "alert(11)"),
+    ("id", "hostile-id"),
+    ("class", "hostile-class"),
+    ("style", "hostile-style"),
+    ("tabindex", "hostile-tabindex"),
+    ("role", "hostile-role"),
+];
+
+//
+// The only event handlers that build_svg emits.
+//
+const HANDLERS: &[&str] = &["showInfo(evt)", "toggleSubtree(evt)"];
+
+//
+// Collects every key and string in a JSON value.
+//
+fn json_strings(value: &Value, strings: &mut Vec<String>) {
+    match value {
+        Value::String(s) => strings.push(s.clone()),
+        Value::Array(values) => {
+            for v in values {
+                json_strings(v, strings);
+            }
+        }
+        Value::Object(map) => {
+            for (k, v) in map {
+                strings.push(k.clone());
+                json_strings(v, strings);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn hostile_values_are_escaped() {
+    let top = env!("CARGO_MANIFEST_DIR");
+    let outdir = env::temp_dir().join(format!("sastopo2svg-escaping-{}", process::id()));
+
+    let mut config = sastopo2svg::Config::new(
+        outdir.to_str().unwrap().to_string(),
+        format!("{}/tests/data/hostile.xml", top),
+    );
+    config.asset_dir = Some(format!("{}/images", top));
+    sastopo2svg::run(&config).expect("failed to render hostile.xml");
+
+    let svg = fs::read_to_string(outdir.join("sastopo.svg")).unwrap();
+    let mut scripts = 0;
+    let mut in_data = false;
+    let mut data = None;
+    let mut values: Vec<String> = Vec::new();
+    let mut attr_values: Vec<String> = Vec::new();
+    for event in EventReader::from_str(&svg) {
+        match event.expect("generated SVG is not well-formed XML") {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                if name.local_name == "script" {
+                    scripts += 1;
+                }
+                in_data = attributes
+                    .iter()
+                    .any(|attr| attr.name.local_name == "id" && attr.value == "viewer-data");
+                let is_vertex = attributes
+                    .iter()
+                    .any(|attr| attr.name.local_name == "class" && attr.value.starts_with("vertex"));
+                for attr in attributes {
+                    if is_vertex {
+                        assert_ne!(attr.name.local_name, "style", "vertex group has a style");
+                    }
+                    if attr.name.local_name.starts_with("on") {
+                        assert!(
+                            HANDLERS.contains(&attr.value.as_str()),
+                            "unexpected event handler {}={:?}",
+                            attr.name.local_name,
+                            attr.value
+                        );
+                    }
+                    attr_values.push(attr.value.clone());
+                    values.push(attr.value);
+                }
+            }
+            XmlEvent::Characters(text) if in_data => {
+                let json = serde_json::from_str::<Value>(&text);
+                data = Some(json.expect("viewer data is not valid JSON"));
+            }
+            XmlEvent::Characters(text) => values.push(text),
+            XmlEvent::EndElement { .. } => in_data = false,
+            _ => {}
+        }
+    }
+    assert_eq!(scripts, 1, "generated SVG has injected <script> elements");
+    let data = data.expect("generated SVG has no viewer data");
+    json_strings(&data, &mut values);
+
+    for hostile in HOSTILE_VALUES {
+        assert!(
+            values.iter().any(|value| value == hostile),
+            "{:?} did not survive as text",
+            hostile
+        );
+    }
+
+    let mut data_strings = Vec::new();
+    json_strings(&data, &mut data_strings);
+    for (name, value) in HOSTILE_PROPERTIES {
+        assert!(
+            data_strings.iter().any(|s| s == name) && data_strings.iter().any(|s| s == value),
+            "property {} is missing from the viewer data",
+            name
+        );
+        assert!(
+            !attr_values.iter().any(|s| s == value),
+            "property {} became an attribute",
+            name
+        );
+    }
+
+    fs::remove_dir_all(&outdir).unwrap();
+}